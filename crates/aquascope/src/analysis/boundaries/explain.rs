@@ -0,0 +1,267 @@
+//! Causal explanations for permission violations.
+//!
+//! A [`PermissionsBoundary`](super::PermissionsBoundary) records _which_
+//! loan refines a path, but the more interesting question for a
+//! reader is usually _why_ that loan is still around. Polonius
+//! answers this with the relation:
+//!
+//! ```text
+//! loan_live_at(Loan, Point) :-
+//!   origin_contains_loan_on_entry(Origin, Loan, Point),
+//!   origin_live_on_entry(Origin, Point).
+//! ```
+//!
+//! and an origin is live at a point because some later use of a
+//! variable mentions it. This module walks the CFG forward from the
+//! boundary, staying within the points where the loan is live, until
+//! it finds such a use. Together with the point where the loan was
+//! issued this gives the familiar “borrowed here … still used here”.
+
+use std::collections::VecDeque;
+
+use rustc_data_structures::fx::{FxHashMap as HashMap, FxHashSet as HashSet};
+use rustc_index::Idx;
+use rustc_middle::mir::Location;
+use rustc_span::Span;
+use rustc_utils::source_map::range::CharRange;
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::analysis::{
+  AquascopeAnalysis, LoanKey,
+  permissions::{
+    Loan, Permissions, PermissionsCtxt, PermissionsData, Point, Variable,
+  },
+};
+
+/// Why a [`Loan`] is refining the permissions of a path at a boundary.
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct LoanExplanation {
+  /// The loan responsible for the missing permissions.
+  pub loan: LoanKey,
+
+  /// Where the loan was issued.
+  pub issued_at: CharRange,
+
+  /// A later use which keeps the loan live at the boundary.
+  pub used_at: CharRange,
+
+  /// Source ranges along a control-flow path from the loan issue,
+  /// through the boundary, to the later use.
+  pub path: Vec<CharRange>,
+
+  /// The MIR location of the later use.
+  #[serde(skip)]
+  pub(crate) use_location: Location,
+}
+
+/// The loan responsible for the first permission that is expected,
+/// but missing, in the given `data`.
+fn refining_loan(
+  expected: Permissions,
+  actual: Permissions,
+  data: &PermissionsData,
+) -> Option<LoanKey> {
+  if expected.read && !actual.read {
+    data.loan_refined.as_read_refinement()
+  } else if expected.write && !actual.write {
+    data.loan_refined.as_write_refinement()
+  } else if expected.drop && !actual.drop {
+    data.loan_drop_refined
+  } else {
+    None
+  }
+}
+
+/// Breadth-first search over the CFG from `from` to the first point satisfying
+/// `is_target`, only passing through points accepted by `can_visit`.
+///
+/// The returned path includes both the `from` and target points.
+fn cfg_path(
+  successors: &HashMap<Point, Vec<Point>>,
+  from: Point,
+  can_visit: impl Fn(Point) -> bool,
+  is_target: impl Fn(Point) -> bool,
+) -> Option<Vec<Point>> {
+  let mut parents: HashMap<Point, Point> = HashMap::default();
+  let mut seen: HashSet<Point> = HashSet::default();
+  let mut queue = VecDeque::from([from]);
+  seen.insert(from);
+
+  while let Some(point) = queue.pop_front() {
+    if point != from && is_target(point) {
+      let mut path = vec![point];
+      let mut curr = point;
+      while let Some(&parent) = parents.get(&curr) {
+        path.push(parent);
+        curr = parent;
+      }
+      path.reverse();
+      return Some(path);
+    }
+
+    for &next in successors.get(&point).into_iter().flatten() {
+      if can_visit(next) && seen.insert(next) {
+        parents.insert(next, point);
+        queue.push_back(next);
+      }
+    }
+  }
+
+  None
+}
+
+/// Does a use of some variable at `point` require that `loan` be live?
+///
+/// This is the case when a variable used (or dropped) at `point` has
+/// an origin in its type which contains the loan.
+fn is_use_of_loan(
+  ctxt: &PermissionsCtxt,
+  uses_at: &HashMap<Point, Vec<Variable>>,
+  loan: Loan,
+  point: Point,
+) -> bool {
+  let facts = ctxt.polonius_input_facts;
+  let Some(origins_at) =
+    ctxt.polonius_output.origin_contains_loan_at.get(&point)
+  else {
+    return false;
+  };
+
+  let Some(vars) = uses_at.get(&point) else {
+    return false;
+  };
+
+  facts
+    .use_of_var_derefs_origin
+    .iter()
+    .chain(facts.drop_of_var_derefs_origin.iter())
+    .filter(|(var, _)| vars.contains(var))
+    .any(|(_, origin)| {
+      origins_at
+        .get(origin)
+        .is_some_and(|loans| loans.contains(&loan))
+    })
+}
+
+fn path_to_ranges(
+  analysis: &AquascopeAnalysis,
+  points: impl IntoIterator<Item = Point>,
+) -> Vec<CharRange> {
+  let ctxt = &analysis.permissions;
+  let mut spans: Vec<Span> = Vec::default();
+  for point in points {
+    if !ctxt.is_point_operational(point) {
+      continue;
+    }
+
+    let span = ctxt.location_to_span(ctxt.point_to_location(point));
+    if span.is_dummy() || span.is_empty() || spans.last() == Some(&span) {
+      continue;
+    }
+
+    spans.push(span);
+  }
+
+  spans
+    .into_iter()
+    .map(|span| analysis.span_to_range(span))
+    .collect()
+}
+
+/// Explains violations within a single body.
+///
+/// The CFG successors and variable uses are shared by all the
+/// boundaries of a body, so they're only gathered once.
+pub(super) struct LoanExplainer<'a, 'tcx> {
+  analysis: &'a AquascopeAnalysis<'tcx>,
+  successors: HashMap<Point, Vec<Point>>,
+  uses_at: HashMap<Point, Vec<Variable>>,
+}
+
+impl<'a, 'tcx> LoanExplainer<'a, 'tcx> {
+  pub(super) fn new(analysis: &'a AquascopeAnalysis<'tcx>) -> Self {
+    let facts = analysis.permissions.polonius_input_facts;
+
+    let mut successors: HashMap<Point, Vec<Point>> = HashMap::default();
+    for &(from, to) in &facts.cfg_edge {
+      successors.entry(from).or_default().push(to);
+    }
+
+    let mut uses_at: HashMap<Point, Vec<Variable>> = HashMap::default();
+    for &(var, p) in facts.var_used_at.iter().chain(facts.var_dropped_at.iter())
+    {
+      uses_at.entry(p).or_default().push(var);
+    }
+
+    LoanExplainer {
+      analysis,
+      successors,
+      uses_at,
+    }
+  }
+
+  /// Explain why the permissions at `point` are missing, if they are
+  /// missing because of a live loan.
+  pub(super) fn explain_violation(
+    &self,
+    expected: Permissions,
+    actual: Permissions,
+    data: &PermissionsData,
+    point: Point,
+  ) -> Option<LoanExplanation> {
+    let analysis = self.analysis;
+    let ctxt = &analysis.permissions;
+    let key = refining_loan(expected, actual, data)?;
+    let loan = Loan::new(*key as usize);
+
+    let issue_point = ctxt
+      .polonius_input_facts
+      .loan_issued_at
+      .iter()
+      .find_map(|&(_, l, p)| (l == loan).then_some(p))?;
+
+    let is_live = |p: Point| {
+      ctxt
+        .polonius_output
+        .loan_live_at
+        .get(&p)
+        .is_some_and(|loans| loans.contains(&loan))
+    };
+
+    let to_use = cfg_path(&self.successors, point, is_live, |p| {
+      is_use_of_loan(ctxt, &self.uses_at, loan, p)
+    })?;
+    let use_point = *to_use.last().unwrap();
+
+    // The boundary is reachable from the issue point, otherwise the
+    // loan could not be live there. If, for whatever reason, this isn't
+    // the case then the path simply starts at the boundary.
+    let to_boundary =
+      cfg_path(&self.successors, issue_point, |_| true, |p| p == point)
+        .unwrap_or_else(|| vec![point]);
+
+    log::debug!(
+      "Explaining loan {loan:?} at {point:?}: issued at {issue_point:?}, used at {use_point:?}"
+    );
+
+    let issue_span =
+      ctxt.location_to_span(ctxt.loan_to_borrow(loan).reserve_location());
+    let use_location = ctxt.point_to_location(use_point);
+    let use_span = ctxt.location_to_span(use_location);
+
+    let path = path_to_ranges(
+      analysis,
+      to_boundary.into_iter().chain(to_use.into_iter().skip(1)),
+    );
+
+    Some(LoanExplanation {
+      loan: key,
+      issued_at: analysis.span_to_range(issue_span),
+      used_at: analysis.span_to_range(use_span),
+      path,
+      use_location,
+    })
+  }
+}
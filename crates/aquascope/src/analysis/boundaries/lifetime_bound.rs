@@ -0,0 +1,138 @@
+//! Suggestions for missing lifetime bounds.
+//!
+//! When an abstract region flows into another without a user-specified
+//! outlives relation, the flow boundary is marked as a
+//! [`FlowEdgeKind::MissingUniversalConstraint`](crate::analysis::permissions::flow::FlowEdgeKind).
+//! The fix is almost always to add the missing relation to the function
+//! signature, which is what we suggest here.
+//!
+//! ```ignore
+//! fn ident<'a, 'b>(a: &'a i32, b: &'b i32) -> &'a i32 {
+//!   b // suggestion: `where 'b: 'a`
+//! }
+//! ```
+//!
+//! The clause is placed at the end of the signature, or appended to its
+//! existing where-clause, the same way rustc places its own suggestions.
+
+use itertools::Itertools;
+use rustc_utils::{SpanExt, source_map::range::CharRange};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::analysis::{AquascopeAnalysis, permissions::Origin};
+
+/// A where-clause that would make an invalid abstract flow valid.
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct LifetimeBoundSuggestion {
+  /// The text to insert, e.g. ` where 'b: 'a`, or `, 'b: 'a`
+  /// if the signature already has a where-clause.
+  pub where_clause: String,
+
+  /// The range replaced by the `where_clause`. It's empty, unless it
+  /// covers the trailing comma of an existing where-clause.
+  pub range: CharRange,
+}
+
+/// Suggest the where-clause needed for abstract regions in `from`
+/// to flow into `to`. Returns `None` if any of the involved regions
+/// was not written by the user, in which case there's nothing to name.
+pub(super) fn suggest_lifetime_bound(
+  analysis: &AquascopeAnalysis,
+  from: Origin,
+  to: Origin,
+) -> Option<LifetimeBoundSuggestion> {
+  let ctxt = &analysis.permissions;
+  let tcx = ctxt.tcx;
+
+  let missing = ctxt.region_flows().missing_universal_constraints(from, to);
+  if missing.is_empty() {
+    return None;
+  }
+
+  let names = &analysis.region_names;
+  let bounds = missing
+    .into_iter()
+    .map(|(longer, shorter)| {
+      let longer = names.lifetime_of_scc(ctxt, longer)?;
+      let shorter = names.lifetime_of_scc(ctxt, shorter)?;
+      Some(format!("{longer}: {shorter}"))
+    })
+    .collect::<Option<Vec<_>>>()?;
+
+  let generics = tcx.hir_get_generics(ctxt.def_id.expect_local())?;
+  let body_span = ctxt.body_with_facts.body.span;
+  let tail = generics.tail_span_for_predicate_suggestion();
+  let span = tail.as_local(body_span).unwrap_or(tail);
+
+  log::debug!("Suggesting lifetime bounds {bounds:?} for {from:?} -> {to:?}");
+
+  Some(LifetimeBoundSuggestion {
+    where_clause: format!(
+      "{} {}",
+      generics.add_where_or_trailing_comma(),
+      bounds.into_iter().unique().join(", ")
+    ),
+    range: analysis.span_to_range(span),
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use fluid_let::fluid_set;
+
+  use super::*;
+  use crate::{
+    analysis::{
+      boundaries::compute_permission_boundaries,
+      permissions::ENABLE_FLOW_PERMISSIONS,
+    },
+    test_utils as tu,
+  };
+
+  fn suggested_clauses(code: &str) -> Vec<String> {
+    let mut clauses = Vec::default();
+    tu::compile_normal(code, |tcx| {
+      tu::for_each_body(tcx, |body_id, _| {
+        fluid_set!(ENABLE_FLOW_PERMISSIONS, true);
+        let analysis = AquascopeAnalysis::new(tcx, body_id);
+        let boundaries = compute_permission_boundaries(&analysis).unwrap();
+        clauses.extend(
+          boundaries
+            .into_iter()
+            .filter_map(|b| b.expecting_flow?.missing_bound)
+            .map(|suggestion| suggestion.where_clause),
+        );
+      });
+    });
+    clauses
+  }
+
+  #[test]
+  fn suggest_new_where_clause() {
+    let clauses = suggested_clauses(
+      r"
+fn ident<'a, 'b>(a: &'a i32, b: &'b i32) -> &'a i32 {
+  b
+}
+",
+    );
+    assert_eq!(clauses, vec![" where 'b: 'a"]);
+  }
+
+  #[test]
+  fn suggest_extending_where_clause() {
+    let clauses = suggested_clauses(
+      r"
+fn ident<'a, 'b, T>(a: &'a T, b: &'b T) -> &'a T
+where
+  T: Copy,
+{
+  b
+}
+",
+    );
+    assert_eq!(clauses, vec![", 'b: 'a"]);
+  }
+}
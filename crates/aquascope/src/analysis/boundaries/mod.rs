@@ -74,7 +74,10 @@
 //! expansion. One last hiccup in the process of finding source spans is the span information
 //! available in the HIR. For this macro, if you just look at the source location it will point
 //! to somewhere from within rustc. We utilize the [`SpanExt::as_local`] method to sanitize spans
-//! and lift them back to original source code.
+//! and lift them back to original source code. User-defined macros are more forgiving,
+//! the tokens passed as arguments to, e.g., `my_push!(v, 1)` keep their source spans in the
+//! expansion, so the uses of `v` get a boundary at `v` itself. Places introduced by the
+//! macro body have expanded spans, and are hidden as any other desugaring.
 //! Lastly, the struct [`ExpectedPermissions`] has a series of construction methods
 //! which show concisely when certain permissions are expected for the respective uses.
 //! In this case, a shared borrow only requires the Read permission.
@@ -116,9 +119,15 @@
 //! variable. As you can see in the above mini-example, compiler temporaries are
 //! introduced that we don't want to consider. After finding these so-called
 //! “candidate places” we need to actually pick one that belongs to the _specific_ usage
-//! we're interested in (more on this in the example). To date, every bug reported for
+//! we're interested in (more on this in the example). Historically, every bug reported for
 //! the boundaries analysis had to do with picking a place from the list of candidates.
 //!
+//! The pick is made by the [`IRMapper::resolve_place`], which matches the shape of the
+//! HIR path, its base variable and the sequence of field accesses, indices, and
+//! dereferences, against that of each candidate. The resolved place comes with a
+//! [`Confidence`] in the match. Only if no candidate shares the base variable of the path
+//! do we fall back to picking a local by the similarity of its name to the path source.
+//!
 //! ## Example
 //!
 //! Returning to our example function, remember that we have two `PathBoundaries`,
@@ -162,7 +171,8 @@
 //! bounds check, but the bounds check is on a different `Place` than what we're
 //! after). One additional thing to note, however, is that for the method call our
 //! resolved `Place` corresponds to `(*s)`, different from the path `s` visible
-//! in the source code.
+//! in the source code. The resolver accounts for this through the auto-deref
+//! adjustment the type checker records on the receiver `s`.
 //!
 //! For our example, after this selection we will have an exact `Place` and
 //! `Location` for a path use. To get the actual permissions, we can use the
@@ -173,18 +183,26 @@
 //! The entry location to this process of resolving a HIR path to a MIR place,
 //! and retrieving the permissions can be found in the [`path_to_perm_boundary`] function.
 
+pub(crate) mod explain;
+pub(crate) mod lifetime_bound;
 pub(crate) mod path_visitor;
+pub mod render;
+pub mod suggest;
 
 use anyhow::Result;
 use either::Either;
+use explain::{LoanExplainer, LoanExplanation};
+use lifetime_bound::{LifetimeBoundSuggestion, suggest_lifetime_bound};
 use path_visitor::get_path_boundaries;
-use rustc_hir::HirId;
+use rustc_hir::{
+  Block, BlockCheckMode, ExprKind, HirId, Node, UnOp, UnsafeSource,
+};
 use rustc_middle::{
   mir::{
     Body, Location, Mutability, Operand, Place, Rvalue, Statement,
-    StatementKind,
+    StatementKind, Terminator, TerminatorKind,
   },
-  ty::{TyCtxt, adjustment::AutoBorrowMutability},
+  ty::{self, Ty, TyCtxt},
 };
 use rustc_span::Span;
 use rustc_utils::{
@@ -193,16 +211,22 @@ use rustc_utils::{
 };
 use serde::Serialize;
 use smallvec::{SmallVec, smallvec};
+use suggest::{
+  ENABLE_SUGGESTIONS, ENABLE_SUGGESTIONS_DEFAULT, FixSuggestion, suggest_fixes,
+};
 use ts_rs::TS;
 
 use crate::{
   analysis::{
-    AquascopeAnalysis,
-    ir_mapper::{GatherDepth, IRMapper},
+    AquascopeAnalysis, LoanKey,
+    ir_mapper::{
+      Confidence, GatherDepth, IRMapper, ResolvedPlace, region_name::RegionName,
+    },
     permissions::{
-      ENABLE_FLOW_DEFAULT, ENABLE_FLOW_PERMISSIONS, Origin, Permissions,
-      PermissionsCtxt, PermissionsData, Point, flow::FlowEdgeKind,
+      ENABLE_FLOW_DEFAULT, ENABLE_FLOW_PERMISSIONS, InteriorMutability, Origin,
+      Permissions, PermissionsCtxt, PermissionsData, Point, flow::FlowEdgeKind,
     },
+    summaries::{SummaryLink, summary_link},
   },
   errors,
 };
@@ -216,6 +240,14 @@ pub struct FlowBoundary {
   is_violation: bool,
   flow_context: CharRange,
   kind: FlowEdgeKind,
+  /// Source-level names of the regions in a violating flow.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  from_region: Option<RegionName>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  to_region: Option<RegionName>,
+  /// The lifetime bound that would make a missing universal constraint valid.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  missing_bound: Option<LifetimeBoundSuggestion>,
 }
 
 /// A point where the permissions reality are checked against their expectations.
@@ -225,15 +257,45 @@ pub struct PermissionsBoundary {
   pub location: CharPos,
   #[serde(skip)]
   byte_location: BytePos,
+  /// The path as written in the source, for rendering the boundary as text.
+  #[serde(skip)]
+  pub path_name: Option<String>,
   pub expected: Permissions,
+  pub use_kind: UseKind,
   pub actual: Permissions,
   pub data: PermissionsData,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub expecting_flow: Option<FlowBoundary>,
+  /// Why a live loan is removing the expected permissions, if it is.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub explanation: Option<LoanExplanation>,
+  /// The type that checks the expected write at runtime, if any.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub dynamic_write: Option<InteriorMutability>,
+  /// Is the boundary within an `unsafe` block? There the compiler
+  /// trusts the programmer to uphold some of these permissions.
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pub compiler_unchecked: bool,
+  /// The callee parameter this path is passed to, if it's a call argument.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub summary: Option<SummaryLink>,
+  /// The loan a closure takes when capturing this path by reference.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub capture_loan: Option<LoanKey>,
+  /// Edits which would fix the violation, if this boundary is one.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub suggestions: Vec<FixSuggestion>,
 }
 
 impl PermissionsBoundary {
   pub fn is_violation(&self) -> bool {
+    // Places reached through a raw pointer are never checked. Safe uses
+    // within an `unsafe` block still are, so `compiler_unchecked` is
+    // only an annotation.
+    if self.data.unchecked {
+      return false;
+    }
+
     macro_rules! is_missing {
       ($this:ident, $perm:ident) => {
         ($this.expected.$perm && !$this.actual.$perm)
@@ -249,56 +311,142 @@ impl PermissionsBoundary {
 // ----------------------------------
 // Permission boundaries on path uses
 
+/// How a path is used at a boundary.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, TS)]
+#[ts(export)]
+pub enum UseKind {
+  /// The value of the path is copied.
+  Copy,
+
+  /// The value of the path is moved.
+  Move,
+
+  /// The path is explicitly borrowed, e.g. `&x` or `&mut x`.
+  Borrow,
+
+  /// The path is implicitly borrowed by an adjustment, e.g. the
+  /// auto-ref of a method receiver or an overloaded operand.
+  Reborrow,
+
+  /// The path is written to, e.g. `*x = y` or `x += y`.
+  Write,
+
+  /// The path is overwritten, or initialized, by an assignment.
+  Overwrite,
+
+  /// The discriminant of the path is inspected by a `match`.
+  Discriminant,
+}
+
 #[derive(Copy, Clone, Debug)]
-struct ExpectedPermissions(Permissions);
+struct ExpectedPermissions(Permissions, UseKind);
 
 impl ExpectedPermissions {
   pub fn from_assignment() -> Self {
-    Self(Permissions {
-      read: true,
-      write: true,
-      drop: false,
-    })
+    Self(
+      Permissions {
+        read: true,
+        write: true,
+        drop: false,
+      },
+      UseKind::Write,
+    )
+  }
+
+  /// An assignment which overwrites, or initializes, the path.
+  pub fn from_overwrite() -> Self {
+    Self(
+      Permissions {
+        read: false,
+        write: true,
+        drop: false,
+      },
+      UseKind::Overwrite,
+    )
   }
 
   pub fn from_borrow(mutability: Mutability) -> Self {
-    Self(Permissions {
-      read: true,
-      write: matches!(mutability, Mutability::Mut),
-      drop: false,
-    })
+    Self(
+      Permissions {
+        read: true,
+        write: matches!(mutability, Mutability::Mut),
+        drop: false,
+      },
+      UseKind::Borrow,
+    )
   }
 
-  pub fn from_reborrow(mutability: AutoBorrowMutability) -> Self {
-    Self(Permissions {
-      read: true,
-      write: matches!(mutability, AutoBorrowMutability::Mut { .. }),
-      drop: false,
-    })
+  pub fn from_reborrow(mutability: Mutability) -> Self {
+    Self(
+      Permissions {
+        read: true,
+        write: matches!(mutability, Mutability::Mut),
+        drop: false,
+      },
+      UseKind::Reborrow,
+    )
+  }
+
+  /// An operand of an overloaded operator, passed to the operator
+  /// method as `param`. E.g. `v[i]` passes `&v` to `Index::index`,
+  /// while `a + b` moves both operands into `Add::add`.
+  pub fn from_overloaded_operand(param: Ty, is_copyable: bool) -> Self {
+    match param.kind() {
+      ty::Ref(_, _, mutability) => Self::from_reborrow(*mutability),
+      _ if is_copyable => Self::from_copy(),
+      _ => Self::from_move(),
+    }
   }
 
   pub fn from_move() -> Self {
-    Self(Permissions {
-      read: true,
-      write: false,
-      drop: true,
-    })
+    Self(
+      Permissions {
+        read: true,
+        write: false,
+        drop: true,
+      },
+      UseKind::Move,
+    )
   }
 
   pub fn from_copy() -> Self {
-    Self(Permissions {
-      read: true,
-      write: false,
-      drop: false,
-    })
+    Self(
+      Permissions {
+        read: true,
+        write: false,
+        drop: false,
+      },
+      UseKind::Copy,
+    )
+  }
+
+  /// A write through a shared reference, checked at runtime by
+  /// a type with [`InteriorMutability`]. The reference is taken
+  /// by the auto-ref of a method receiver.
+  pub fn from_dynamic_write() -> Self {
+    Self(
+      Permissions {
+        read: true,
+        write: true,
+        drop: false,
+      },
+      UseKind::Reborrow,
+    )
   }
 
   pub fn from_discriminant() -> Self {
-    Self(Permissions {
-      read: true,
-      write: false,
-      drop: false,
-    })
+    Self(
+      Permissions {
+        read: true,
+        write: false,
+        drop: false,
+      },
+      UseKind::Discriminant,
+    )
+  }
+
+  pub fn use_kind(self) -> UseKind {
+    self.1
   }
 }
 
@@ -328,6 +476,16 @@ struct PathBoundary {
 
   /// The permissions required for the [`Place`] usage.
   pub expected: ExpectedPermissions,
+
+  /// Is the expected write checked at runtime rather than by the compiler?
+  pub dynamic_write: Option<InteriorMutability>,
+
+  /// Does an assignment overwrite the path? The path may then
+  /// be uninitialized, and its old value is never read.
+  pub is_overwrite: bool,
+
+  /// Is the path captured by a closure created here?
+  pub is_capture: bool,
 }
 
 impl std::fmt::Debug for PathBoundary {
@@ -336,10 +494,26 @@ impl std::fmt::Debug for PathBoundary {
       .field("location", &self.location)
       .field("hir_id", &self.hir_id)
       .field("expected", &self.expected)
+      .field("dynamic_write", &self.dynamic_write)
+      .field("is_overwrite", &self.is_overwrite)
+      .field("is_capture", &self.is_capture)
       .finish()
   }
 }
 
+/// Is the node `hir_id` nested within a user-written `unsafe` block?
+fn is_in_unsafe_block(tcx: TyCtxt, hir_id: HirId) -> bool {
+  tcx.hir_parent_iter(hir_id).any(|(_, node)| {
+    matches!(
+      node,
+      Node::Block(Block {
+        rules: BlockCheckMode::UnsafeBlock(UnsafeSource::UserProvided),
+        ..
+      })
+    )
+  })
+}
+
 /// Get all local places within the body, sorted by their similarity to the given `HirId` place.
 fn locals_by_visible_distance<'tcx>(
   tcx: TyCtxt<'tcx>,
@@ -367,27 +541,70 @@ fn locals_by_visible_distance<'tcx>(
   fuzzy_match(&node_str, all_local_places)
 }
 
-// HACK: this is unsatisfying. Ideally, we would be able to take a (resolved) hir::Path
-// and turn it directly into its corresponding mir::Place, I (gavin)
-// haven't found a great way to do this, so for now, we consider all
-// Places occurring inside of a mapped HirId, and for some cases we can
-// remove Places from consideration depending on the hir::Node they came from.
-// TODO: this mechanism needs to be built up and inserted into the IRMapper.
-// We could make this more robust by doing a union from a hir::Path with a
-// mir::Path comparing on *shape*, (number and types of projections).
+/// The outermost built-in place expression of which the path `hir_id` is
+/// the base, e.g. `a[0]` for the path `a`. The path is that of a variable,
+/// but the permissions are those of the place used.
+fn enclosing_place_expr(tcx: TyCtxt, hir_id: HirId) -> HirId {
+  let typeck_res = tcx.typeck(hir_id.owner.def_id);
+  let mut place = hir_id;
+  for (parent_id, parent) in tcx.hir_parent_iter(hir_id) {
+    let Node::Expr(expr) = parent else {
+      break;
+    };
+    match expr.kind {
+      ExprKind::Field(base, _)
+      | ExprKind::Index(base, _, _)
+      | ExprKind::Unary(UnOp::Deref, base)
+        if base.hir_id == place && !typeck_res.is_method_call(expr) =>
+      {
+        place = parent_id;
+      }
+      _ => break,
+    }
+  }
+  place
+}
+
 /// Pick a matching [`Location`] and [`Place`] from the given [`HirId`] use site.
 ///
+/// The place is resolved structurally from the path expression `path_hir_id`
+/// by the [`IRMapper`]. If that fails, the name of a local is matched
+/// against the source of the node `hir_id` where the candidates were found.
+/// Binding patterns are only resolved structurally.
+///
 /// NOTE: candidates are expected to be given as an
 /// [*inorder*](https://en.wikipedia.org/wiki/Tree_traversal) HIR tree traversal.
 fn select_candidate_location<'tcx>(
   tcx: TyCtxt<'tcx>,
   body: &Body<'tcx>,
+  ir_mapper: &IRMapper<'tcx>,
+  path_hir_id: HirId,
   hir_id: HirId,
-  //subtract_from: impl FnOnce() -> Vec<(Location, Place<'tcx>)>,
   candidates: &[(Location, Place<'tcx>)],
-) -> Option<(Location, Place<'tcx>)> {
+) -> Option<ResolvedPlace<'tcx>> {
+  let place_hir_id = enclosing_place_expr(tcx, path_hir_id);
+  if let Some(resolved) = ir_mapper.resolve_place(place_hir_id, candidates) {
+    return Some(resolved);
+  }
+
+  // The name of a binding is that of its own local, not the place it binds.
+  if matches!(tcx.hir_node(path_hir_id), Node::Pat(_)) {
+    return None;
+  }
+
+  log::warn!(
+    "Could not resolve place structurally, falling back to name similarity for {}",
+    tcx.hir_id_to_string(path_hir_id)
+  );
+
+  let fallback = |&(location, place): &(Location, Place<'tcx>)| ResolvedPlace {
+    location,
+    place,
+    confidence: Confidence::Fallback,
+  };
+
   if candidates.len() <= 1 {
-    return candidates.first().copied();
+    return candidates.first().map(fallback);
   }
 
   // From the list of local places sorted by similarity to the given `HirId`,
@@ -397,7 +614,7 @@ fn select_candidate_location<'tcx>(
     candidates
       .iter()
       .find(|(_, can_place)| can_place.local == similar_local.local)
-      .copied()
+      .map(fallback)
   } else {
     // NOTE If no similar local is found, then we can do a "best effort" search.
     // The first usage contains the relevant Local,
@@ -427,7 +644,7 @@ fn select_candidate_location<'tcx>(
     matching_locals
       .rev()
       .max_by_key(|(_, p)| p.projection.len())
-      .copied()
+      .map(fallback)
   }
 }
 
@@ -540,7 +757,7 @@ fn get_flow_permission(
           .any(|&(_f, t, _)| t == from || t == to)
       {
         log::debug!("found flow violation: {fk:?} @ {from:?} -> {to:?}");
-        Some(fk)
+        Some((fk, from, to))
       } else {
         None
       }
@@ -552,10 +769,23 @@ fn get_flow_permission(
   //
   // A brief discussion at:
   // https://github.com/cognitive-engineering-lab/aquascope/pull/51#discussion_r1141095658
-  let kind = flow_violations.next().unwrap_or_else(|| {
-    log::debug!("No flow edge violation found");
-    FlowEdgeKind::Ok
-  });
+  let violation = flow_violations.next();
+
+  let name_of = |origin| analysis.region_names.name_of(ctxt, origin);
+  let (from_region, to_region) = violation
+    .as_ref()
+    .map_or((None, None), |&(_, from, to)| (name_of(from), name_of(to)));
+
+  let (kind, missing_bound) = match violation {
+    Some((kind @ FlowEdgeKind::MissingUniversalConstraint, from, to)) => {
+      (kind, suggest_lifetime_bound(analysis, from, to))
+    }
+    Some((kind, _, _)) => (kind, None),
+    None => {
+      log::debug!("No flow edge violation found");
+      (FlowEdgeKind::Ok, None)
+    }
+  };
 
   let raw_span = ctxt.tcx.hir_span(flow_context);
   let span = raw_span.as_local(body.span).unwrap_or(body.span);
@@ -565,11 +795,19 @@ fn get_flow_permission(
     is_violation: !kind.is_valid_flow(),
     flow_context,
     kind,
+    from_region,
+    to_region,
+    missing_bound,
   })
 }
 
 /// Find all of the places used at the MIR-level of the given HIR node. This
 /// builds our set of candidate places that we consider for boundary resolution.
+///
+/// The bindings of a borrowed scrutinee, e.g. `match &opt`, borrow places
+/// behind the temporary holding the reference. These aren't source visible,
+/// but as bindings are only resolved structurally, with `is_binding` any
+/// borrowed place is a candidate.
 #[allow(clippy::wildcard_in_or_patterns)]
 fn paths_at_hir_id<'tcx>(
   tcx: TyCtxt<'tcx>,
@@ -577,6 +815,7 @@ fn paths_at_hir_id<'tcx>(
   ir_mapper: &IRMapper<'tcx>,
   hir_id: HirId,
   is_lhs: bool,
+  is_binding: bool,
 ) -> Option<Vec<(Location, Place<'tcx>)>> {
   type UsedPlace<'tcx> = (Location, Place<'tcx>);
   type UsedPlaces<'tcx> = SmallVec<[UsedPlace<'tcx>; 3]>;
@@ -614,6 +853,7 @@ fn paths_at_hir_id<'tcx>(
          => maybe_in_op!(loc, op),
 
       // Given place cases.
+      Rvalue::Ref(_, _, place) if is_binding => smallvec![(loc, *place)],
       Rvalue::Ref(_, _, place)
         | Rvalue::Discriminant(place)
         | Rvalue::CopyForDeref(place)
@@ -688,13 +928,36 @@ fn paths_at_hir_id<'tcx>(
     }
   };
 
+  // Places passed directly to a call, or assigned its result, are
+  // only mentioned in the terminator. This is common for desugared
+  // calls, e.g. the head of a `for` loop moved into `into_iter`. The
+  // operands of calls written in the source are resolved from their
+  // own statements instead, so only desugared nodes look here.
+  let is_desugared = tcx.hir_span(hir_id).desugaring_kind().is_some();
+  let look_in_terminator =
+    |term: &Terminator<'tcx>, loc: Location| -> UsedPlaces {
+      match &term.kind {
+        TerminatorKind::Call { destination, .. }
+          if is_lhs && destination.is_source_visible(tcx, body) =>
+        {
+          smallvec![(loc, *destination)]
+        }
+        TerminatorKind::Call { args, .. } if !is_lhs => args
+          .iter()
+          .filter_map(|arg| maybe_in_op(tcx, body, loc, &arg.node))
+          .collect(),
+        _ => smallvec![],
+      }
+    };
+
   let mir_locations = mir_locations_opt?
     .values()
     .flat_map(|loc| {
       log::debug!("looking at {loc:?}");
       match body.stmt_at(loc) {
         Either::Left(stmt) => look_in_statement(stmt, loc),
-        Either::Right(_term) => smallvec![],
+        Either::Right(term) if is_desugared => look_in_terminator(term, loc),
+        Either::Right(_) => smallvec![],
       }
     })
     .collect::<Vec<_>>();
@@ -705,6 +968,7 @@ fn paths_at_hir_id<'tcx>(
 fn path_to_perm_boundary(
   path_boundary: PathBoundary,
   analysis: &AquascopeAnalysis<'_>,
+  explainer: &LoanExplainer,
 ) -> Option<PermissionsBoundary> {
   let ctxt = &analysis.permissions;
   let ir_mapper = &analysis.ir_mapper;
@@ -717,21 +981,38 @@ fn path_to_perm_boundary(
     tcx.hir_id_to_string(path_boundary.hir_id)
   );
 
+  let is_binding = matches!(tcx.hir_node(hir_id), Node::Pat(_));
   let search_at_hir_id = |hir_id| {
-    let path_locations =
-      paths_at_hir_id(tcx, body, ir_mapper, hir_id, path_boundary.is_lhs)?;
-
-    let (loc, place) =
-      select_candidate_location(tcx, body, hir_id, &path_locations)?;
+    let path_locations = paths_at_hir_id(
+      tcx,
+      body,
+      ir_mapper,
+      hir_id,
+      path_boundary.is_lhs,
+      is_binding,
+    )?;
+
+    let ResolvedPlace {
+      location: loc,
+      place,
+      confidence,
+    } = select_candidate_location(
+      tcx,
+      body,
+      ir_mapper,
+      path_boundary.hir_id,
+      hir_id,
+      &path_locations,
+    )?;
 
     let point = ctxt.location_to_point(loc);
     let path = ctxt.place_to_path(&place);
 
     log::debug!(
-      "Chosen place at location {place:#?} {loc:#?} ({point:?},{path:?})\nOther options: {path_locations:#?}"
+      "Chosen place ({confidence:?}) at location {place:#?} {loc:#?} ({point:?},{path:?})\nOther options: {path_locations:#?}"
     );
 
-    Some((point, path))
+    Some((loc, point, path, place))
   };
 
   // For a given Path, the MIR location may not be immediately associated with it.
@@ -745,14 +1026,63 @@ fn path_to_perm_boundary(
         search_at_hir_id(hir_id)
       })
     })
-    .map(|(point, path)| {
+    .map(|(loc, point, path, place)| {
       let data = ctxt.permissions_data_at_point(path, point);
       let expected = path_boundary.expected;
-      let actual = data.permissions_ignore_liveness();
+      let dynamic_write = path_boundary
+        .dynamic_write
+        .filter(|_| data.interior_mutability.is_some());
+      let actual = if dynamic_write.is_some() {
+        data.permissions_with_dynamic_writes()
+      } else if path_boundary.is_overwrite {
+        data.permissions_for_overwrite()
+      } else {
+        data.permissions_ignore_liveness()
+      };
 
       let expecting_flow =
         get_flow_permission(analysis, path_boundary.flow_context, hir_id);
 
+      let expected_perms: Permissions = expected.into();
+      let explanation = if expected_perms.is_subset(actual) {
+        None
+      } else {
+        explainer.explain_violation(expected_perms, actual, &data, point)
+      };
+
+      let compiler_unchecked = is_in_unsafe_block(tcx, hir_id);
+
+      let summary = summary_link(tcx, tcx.typeck_body(ctxt.body_id), hir_id);
+
+      let capture_loan = if path_boundary.is_capture {
+        ctxt
+          .polonius_input_facts
+          .loan_issued_at
+          .iter()
+          .find_map(|(_, loan, p)| {
+            (ctxt.point_to_location(*p) == loc).then(|| LoanKey::from(loan))
+          })
+      } else {
+        None
+      };
+
+      let suggestions = if ENABLE_SUGGESTIONS
+        .copied()
+        .unwrap_or(ENABLE_SUGGESTIONS_DEFAULT)
+      {
+        suggest_fixes(
+          analysis,
+          hir_id,
+          place,
+          expected.into(),
+          actual,
+          &data,
+          explanation.as_ref(),
+        )
+      } else {
+        Vec::default()
+      };
+
       log::debug!("Permissions data for {}:\n{actual:#?}\n{expected:#?}\n{expecting_flow:#?}", tcx.hir_id_to_string(path_boundary.hir_id));
 
       let span = path_boundary
@@ -771,10 +1101,18 @@ fn path_to_perm_boundary(
       PermissionsBoundary {
         location,
         byte_location,
+        path_name: place.to_string(tcx, body),
         expected: expected.into(),
+        use_kind: expected.use_kind(),
         actual,
         data,
         expecting_flow,
+        explanation,
+        dynamic_write,
+        compiler_unchecked,
+        summary,
+        capture_loan,
+        suggestions,
       }
     });
 
@@ -794,9 +1132,10 @@ pub fn compute_permission_boundaries(
 ) -> Result<Vec<PermissionsBoundary>> {
   let ctxt = &analysis.permissions;
 
+  let explainer = LoanExplainer::new(analysis);
   let path_use_points = get_path_boundaries(ctxt)?
     .into_iter()
-    .filter_map(|pb| path_to_perm_boundary(pb, analysis));
+    .filter_map(|pb| path_to_perm_boundary(pb, analysis, &explainer));
 
   // FIXME: we need a more robust way of filtering by "first error".
   // here (and in the stepper) we do this by diagnostic span from rustc
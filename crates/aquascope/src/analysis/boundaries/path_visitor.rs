@@ -3,22 +3,24 @@
 use anyhow::{Result, bail};
 use fluid_let::{fluid_let, fluid_set};
 use rustc_hir::{
-  Block, Body, Expr, ExprKind, HirId, Path, QPath, Stmt, UnOp,
+  Block, Body, ByRef, Closure, ClosureKind, Expr, ExprKind, HirId, MatchSource,
+  Node, Pat, PatKind, Path, QPath, Stmt, UnOp,
   def::Res,
   intravisit::{self, Visitor},
 };
 use rustc_middle::{
   hir::nested_filter::OnlyBodies,
+  mir::Mutability,
   ty::{
-    TyCtxt, TypeckResults, TypingEnv,
-    adjustment::{Adjust, AutoBorrow},
+    self, BorrowKind, TyCtxt, TypeckResults, TypingEnv, UpvarCapture,
+    adjustment::{Adjust, Adjustment, AutoBorrow, DerefAdjustKind},
   },
 };
-use rustc_span::Span;
+use rustc_span::{ExpnKind, MacroKind, Span};
 use rustc_utils::TyExt;
 
 use super::{ExpectedPermissions, PathBoundary};
-use crate::analysis::permissions::PermissionsCtxt;
+use crate::analysis::permissions::{InteriorMutability, PermissionsCtxt};
 
 // The current region flow context for outer statements and returns.
 fluid_let!(pub static FLOW_CONTEXT: HirId);
@@ -31,13 +33,23 @@ struct HirExprScraper<'tcx> {
   unsupported_feature: Option<(Span, String)>,
 }
 
-impl HirExprScraper<'_> {
+impl<'tcx> HirExprScraper<'tcx> {
   fn get_adjusted_permissions(&self, expr: &Expr) -> ExpectedPermissions {
     let ty_adj = self.typeck_res.expr_ty_adjusted(expr);
     let adjs = self.typeck_res.expr_adjustments(expr);
 
     log::debug!("Path TY-ADJ: {:#?} from {:#?}", ty_adj, adjs);
 
+    // An overloaded auto-deref, e.g. of an `Rc` for a field access,
+    // borrows the path to call `Deref::deref` or `DerefMut::deref_mut`.
+    if let Some(Adjustment {
+      kind: Adjust::Deref(DerefAdjustKind::Overloaded(overloaded)),
+      ..
+    }) = adjs.first()
+    {
+      return ExpectedPermissions::from_reborrow(overloaded.mutbl);
+    }
+
     let is_auto_borrow = adjs.iter().find_map(|adj| {
       if let Adjust::Borrow(AutoBorrow::Ref(m)) = adj.kind {
         Some(m)
@@ -47,7 +59,7 @@ impl HirExprScraper<'_> {
     });
 
     if let Some(mutability) = is_auto_borrow {
-      return ExpectedPermissions::from_reborrow(mutability);
+      return ExpectedPermissions::from_reborrow(mutability.into());
     }
 
     // At this point the usage is either a move or a copy. We
@@ -59,6 +71,188 @@ impl HirExprScraper<'_> {
       ExpectedPermissions::from_move()
     }
   }
+
+  /// Visit the operands of the overloaded operator `expr`.
+  ///
+  /// The operator is a call to a trait method, e.g. `v[i]` is
+  /// `*Index::index(&v, i)` and `a + b` is `Add::add(a, b)`, so
+  /// place operands expect the permissions of the method parameters.
+  fn visit_overloaded_operands(
+    &mut self,
+    expr: &Expr,
+    operands: &[&'tcx Expr<'tcx>],
+    flow_context: HirId,
+  ) {
+    // The parameters are those of the called instance, e.g.
+    // `Rhs = &str` in `impl Add<&str> for String`.
+    let sig =
+      self
+        .typeck_res
+        .type_dependent_def_id(expr.hir_id)
+        .map(|def_id| {
+          let args = self.typeck_res.node_args(expr.hir_id);
+          self
+            .tcx
+            .fn_sig(def_id)
+            .instantiate(self.tcx, args)
+            .skip_norm_wip()
+            .skip_binder()
+        });
+
+    for (i, operand) in operands.iter().enumerate() {
+      let param = sig.and_then(|sig| sig.inputs().get(i).copied());
+      match param {
+        Some(param)
+          if operand.is_syntactic_place_expr()
+            && !operand.span.from_expansion() =>
+        {
+          let is_copyable = self
+            .typeck_res
+            .expr_ty(operand)
+            .is_copyable(self.tcx, self.typing_env);
+          let pb = PathBoundary {
+            location: operand.span.shrink_to_lo(),
+            hir_id: operand.hir_id,
+            flow_context,
+            is_lhs: false,
+            expected: ExpectedPermissions::from_overloaded_operand(
+              param,
+              is_copyable,
+            ),
+            dynamic_write: None,
+            is_overwrite: false,
+            is_capture: false,
+          };
+          self.data.push(pb);
+        }
+        _ => self.visit_expr(operand),
+      }
+    }
+  }
+
+  /// Visit the source-visible operand of a desugared call, e.g. the
+  /// head of a `for` loop in `IntoIterator::into_iter(head)`. These
+  /// calls take their operand by value, moving or copying a place.
+  fn visit_desugared_operand(
+    &mut self,
+    operand: &'tcx Expr<'tcx>,
+    flow_context: HirId,
+  ) {
+    if operand.is_syntactic_place_expr() && !operand.span.from_expansion() {
+      let pb = PathBoundary {
+        location: operand.span.shrink_to_lo(),
+        hir_id: operand.hir_id,
+        flow_context,
+        is_lhs: false,
+        expected: self.get_adjusted_permissions(operand),
+        dynamic_write: None,
+        is_overwrite: false,
+        is_capture: false,
+      };
+      self.data.push(pb);
+    } else {
+      self.visit_expr(operand);
+    }
+  }
+
+  /// The interior mutability written to by calling `func`,
+  /// if it is a path to a method such as `RefCell::borrow_mut`.
+  fn dynamic_write_callee(&self, func: &Expr) -> Option<InteriorMutability> {
+    match self.typeck_res.expr_ty(func).kind() {
+      ty::FnDef(def_id, _) => {
+        InteriorMutability::of_write_method(self.tcx, *def_id)
+      }
+      _ => None,
+    }
+  }
+
+  /// Push the boundary of the binding pattern `binding`.
+  fn visit_binding(&mut self, binding: &Pat, hir_id: HirId, ident: Span) {
+    let flow_context = FLOW_CONTEXT.copied().unwrap_or(hir_id);
+    let mode =
+      self
+        .typeck_res
+        .extract_binding_mode(self.tcx.sess, hir_id, binding.span);
+
+    let is_root = !matches!(
+      self.tcx.parent_hir_node(hir_id),
+      Node::Pat(_) | Node::PatField(_)
+    );
+    let is_adjusted = self
+      .typeck_res
+      .pat_adjustments()
+      .get(hir_id)
+      .is_some_and(|adjustments| !adjustments.is_empty());
+
+    if is_root && matches!(mode.0, ByRef::No) && !is_adjusted {
+      return;
+    }
+
+    let expected = match mode.0 {
+      ByRef::Yes(_, mutability) => ExpectedPermissions::from_borrow(mutability),
+      ByRef::No
+        if self
+          .typeck_res
+          .pat_ty(binding)
+          .is_copyable(self.tcx, self.typing_env) =>
+      {
+        ExpectedPermissions::from_copy()
+      }
+      ByRef::No => ExpectedPermissions::from_move(),
+    };
+
+    let pb = PathBoundary {
+      location: ident.shrink_to_lo(),
+      hir_id,
+      flow_context,
+      is_lhs: false,
+      expected,
+      dynamic_write: None,
+      is_overwrite: false,
+      is_capture: false,
+    };
+    self.data.push(pb);
+  }
+
+  /// Is `span` written as an argument of the bang macro which expanded
+  /// into `expansion`, e.g. `v` in `my_push!(v, 1)`? The tokens of a
+  /// macro argument keep their source spans, unlike the code around them.
+  fn is_macro_argument(span: Span, expansion: Span) -> bool {
+    !span.from_expansion()
+      && matches!(
+        expansion.ctxt().outer_expn_data().kind,
+        ExpnKind::Macro(MacroKind::Bang, _)
+      )
+  }
+
+  /// Is `expr` a place of a local reached only through built-in
+  /// field accesses, indices, and dereferences?
+  fn is_builtin_place(&self, expr: &Expr) -> bool {
+    let overloaded_deref =
+      self.typeck_res.expr_adjustments(expr).iter().any(|adj| {
+        matches!(
+          adj.kind,
+          Adjust::Deref(DerefAdjustKind::Overloaded(_) | DerefAdjustKind::Pin)
+        )
+      });
+
+    if self.typeck_res.is_method_call(expr) || overloaded_deref {
+      return false;
+    }
+
+    match expr.kind {
+      ExprKind::Path(QPath::Resolved(
+        _,
+        Path {
+          res: Res::Local(_), ..
+        },
+      )) => true,
+      ExprKind::Field(inner, _)
+      | ExprKind::Index(inner, _, _)
+      | ExprKind::Unary(UnOp::Deref, inner) => self.is_builtin_place(inner),
+      _ => false,
+    }
+  }
 }
 
 impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
@@ -81,6 +275,21 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
     intravisit::walk_stmt(self, stmt);
   }
 
+  // Bindings within a pattern take their value from a place of the
+  // scrutinee, by moving, copying, or borrowing it. A binding of the
+  // entire scrutinee by value is already covered by the boundary of
+  // the scrutinee expression itself.
+  fn visit_pat(&mut self, pat: &'tcx Pat<'tcx>) {
+    if let PatKind::Binding(_, hir_id, ident, _) = pat.kind
+      && !ident.span.from_expansion()
+    {
+      self.visit_binding(pat, hir_id, ident.span);
+    }
+
+    // Patterns can contain expressions, e.g. a match guard.
+    intravisit::walk_pat(self, pat);
+  }
+
   fn visit_block(&mut self, block: &'tcx Block) {
     for stmt in block.stmts.iter() {
       self.visit_stmt(stmt);
@@ -99,11 +308,109 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
     log::debug!("visiting {}\n\n", self.tcx.hir_id_to_string(hir_id));
 
     match expr.kind {
+      // Coroutines, i.e. `async` blocks and the body of an `async fn`,
+      // are analyzed as bodies of their own.
+      ExprKind::Closure(Closure {
+        kind: ClosureKind::Coroutine(_),
+        ..
+      }) => {}
+
+      // A closure captures the places it mentions when it is created,
+      // by moving or borrowing them, which happens in this body. The
+      // closure body itself is analyzed as a body of its own.
+      ExprKind::Closure(Closure { def_id, .. }) => {
+        for capture in self.tcx.closure_captures(*def_id) {
+          let Some(path_expr_id) = capture.info.path_expr_id else {
+            continue;
+          };
+
+          let span = self.tcx.hir_span(path_expr_id);
+          if span.from_expansion() {
+            continue;
+          }
+
+          let expected = match capture.info.capture_kind {
+            UpvarCapture::ByRef(BorrowKind::Mutable) => {
+              ExpectedPermissions::from_borrow(Mutability::Mut)
+            }
+            // A unique immutable borrow is taken of a `&mut` reference
+            // written through, which doesn't need the reference to be `mut`.
+            UpvarCapture::ByRef(
+              BorrowKind::Immutable | BorrowKind::UniqueImmutable,
+            ) => ExpectedPermissions::from_borrow(Mutability::Not),
+            // A `use` closure clones the place.
+            UpvarCapture::ByUse => ExpectedPermissions::from_copy(),
+            UpvarCapture::ByValue
+              if capture.place.ty().is_copyable(self.tcx, self.typing_env) =>
+            {
+              ExpectedPermissions::from_copy()
+            }
+            UpvarCapture::ByValue => ExpectedPermissions::from_move(),
+          };
+
+          let pb = PathBoundary {
+            location: span.shrink_to_lo(),
+            hir_id: path_expr_id,
+            flow_context,
+            is_lhs: false,
+            expected,
+            dynamic_write: None,
+            is_overwrite: false,
+            is_capture: true,
+          };
+          self.data.push(pb);
+        }
+      }
+
+      // An `.await` desugars into a loop polling the future, of
+      // which only the awaited expression is visible in the source.
+      ExprKind::Match(into_future, _, MatchSource::AwaitDesugar) => {
+        self.visit_expr(into_future);
+      }
+
+      // A `for` loop desugars into a match binding the iterator
+      // `IntoIterator::into_iter(head)`, which holds the loop. Of these,
+      // only the head and the loop body are visible in the source.
+      ExprKind::Match(
+        Expr {
+          kind: ExprKind::Call(_, [head]),
+          ..
+        },
+        [iter_arm],
+        MatchSource::ForLoopDesugar,
+      ) => {
+        self.visit_desugared_operand(head, flow_context);
+        self.visit_expr(iter_arm.body);
+      }
+
+      // Each iteration matches on `Iterator::next(&mut iter)`, where
+      // the loop pattern binds from the returned `Option` rather than
+      // from a place in the source.
+      ExprKind::Match(_, arms, MatchSource::ForLoopDesugar) => {
+        for arm in arms.iter() {
+          self.visit_expr(arm.body);
+        }
+      }
+
+      // `expr?` desugars into a match on `Try::branch(expr)`, whose arms
+      // only bind the output or residual of the hidden call.
+      ExprKind::Match(
+        Expr {
+          kind: ExprKind::Call(_, [operand]),
+          ..
+        },
+        _,
+        MatchSource::TryDesugar(_),
+      ) => {
+        self.visit_desugared_operand(operand, flow_context);
+      }
+
       // Method calls are a form of type-deref coercion which can
       // rely on the adjusted permissions rather than needing to
       // inspect the function signature.
       ExprKind::Match(discr, arms, _)
-        if discr.is_place_expr(|e| !matches!(e.kind, ExprKind::Lit(_))) =>
+        if !discr.span.from_expansion()
+          && discr.is_place_expr(|e| !matches!(e.kind, ExprKind::Lit(_))) =>
       {
         let expected = ExpectedPermissions::from_discriminant();
         let pb = PathBoundary {
@@ -112,6 +419,9 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
           flow_context,
           is_lhs: false,
           expected,
+          dynamic_write: None,
+          is_overwrite: false,
+          is_capture: false,
         };
 
         self.data.push(pb);
@@ -121,17 +431,35 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
         }
       }
 
+      // A method call expanded from a macro, e.g. `$v.push($x)`, still
+      // uses its receiver if that was passed to the macro.
       ExprKind::MethodCall(_, rcvr, args, fn_span)
-        if !fn_span.from_expansion()
+        if (!fn_span.from_expansion()
+          || Self::is_macro_argument(rcvr.span, fn_span))
           && rcvr.is_place_expr(|e| !matches!(e.kind, ExprKind::Lit(_))) =>
       {
-        let expected = self.get_adjusted_permissions(rcvr);
+        // Methods such as `RefCell::borrow_mut` only need a shared
+        // reference, but they write to the cell's contents.
+        let dynamic_write =
+          self.typeck_res.type_dependent_def_id(expr.hir_id).and_then(
+            |def_id| InteriorMutability::of_write_method(self.tcx, def_id),
+          );
+
+        let expected = if dynamic_write.is_some() {
+          ExpectedPermissions::from_dynamic_write()
+        } else {
+          self.get_adjusted_permissions(rcvr)
+        };
+
         let pb = PathBoundary {
           location: rcvr.span,
           hir_id: rcvr.hir_id,
           flow_context,
           is_lhs: false,
           expected,
+          dynamic_write,
+          is_overwrite: false,
+          is_capture: false,
         };
 
         self.data.push(pb);
@@ -141,6 +469,51 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
         }
       }
 
+      // The same methods called by path, e.g. `RefCell::borrow_mut(&c)`,
+      // take the cell as their first argument.
+      ExprKind::Call(func, [rcvr, args @ ..])
+        if self.dynamic_write_callee(func).is_some() =>
+      {
+        let place = match rcvr.kind {
+          ExprKind::AddrOf(_, _, inner) => inner,
+          _ => rcvr,
+        };
+
+        if place.is_syntactic_place_expr() && !place.span.from_expansion() {
+          let pb = PathBoundary {
+            location: place.span.shrink_to_lo(),
+            hir_id: place.hir_id,
+            flow_context,
+            is_lhs: false,
+            expected: ExpectedPermissions::from_dynamic_write(),
+            dynamic_write: self.dynamic_write_callee(func),
+            is_overwrite: false,
+            is_capture: false,
+          };
+          self.data.push(pb);
+        } else {
+          self.visit_expr(rcvr);
+        }
+
+        for a in args.iter() {
+          self.visit_expr(a);
+        }
+      }
+
+      ExprKind::Index(base, index, _)
+        if self.typeck_res.is_method_call(expr) =>
+      {
+        self.visit_overloaded_operands(expr, &[base, index], flow_context);
+      }
+
+      ExprKind::Unary(_, operand) if self.typeck_res.is_method_call(expr) => {
+        self.visit_overloaded_operands(expr, &[operand], flow_context);
+      }
+
+      ExprKind::Binary(_, lhs, rhs) if self.typeck_res.is_method_call(expr) => {
+        self.visit_overloaded_operands(expr, &[lhs, rhs], flow_context);
+      }
+
       ExprKind::AddrOf(_, mutability, inner)
         if inner.is_syntactic_place_expr() && !inner.span.from_expansion() =>
       {
@@ -152,6 +525,9 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
           is_lhs: false,
           location: inner.span.shrink_to_lo(),
           expected: ExpectedPermissions::from_borrow(mutability),
+          dynamic_write: None,
+          is_overwrite: false,
+          is_capture: false,
         };
 
         self.data.push(pb);
@@ -168,13 +544,16 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
         },
         rhs,
         _,
-      ) => {
+      ) if !lhs.span.from_expansion() => {
         let pb = PathBoundary {
           location: lhs.span.shrink_to_lo(),
           hir_id: lhs.hir_id,
           flow_context,
           is_lhs: true,
           expected: ExpectedPermissions::from_assignment(),
+          dynamic_write: None,
+          is_overwrite: false,
+          is_capture: false,
         };
         self.data.push(pb);
         self.visit_expr(rhs);
@@ -191,13 +570,34 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
       // ```
       //
       // `s` would not have write permissions because it is not yet initialized.
-      // For now, the LHS is simply ignored from the boundaries analysis.
+      // The boundary is marked as an overwrite, for which an uninitialized
+      // path is writeable (see `PermissionsData::permissions_for_overwrite`).
+      ExprKind::Assign(lhs, rhs, _)
+        if self.is_builtin_place(lhs) && !lhs.span.from_expansion() =>
+      {
+        let pb = PathBoundary {
+          location: lhs.span.shrink_to_lo(),
+          hir_id: lhs.hir_id,
+          flow_context,
+          is_lhs: true,
+          expected: ExpectedPermissions::from_overwrite(),
+          dynamic_write: None,
+          is_overwrite: true,
+          is_capture: false,
+        };
+        self.data.push(pb);
+        self.visit_expr(rhs);
+      }
+
+      // Assignments through an overloaded operator, e.g. `v[0] = x`
+      // for a `Vec`, write through the returned reference instead.
+      // The operator's operands are visited for the borrow it takes.
       ExprKind::Assign(lhs, rhs, _) => {
-        log::debug!("ASSIGN: ignoring LHS {lhs:#?}");
+        self.visit_expr(lhs);
         self.visit_expr(rhs);
       }
 
-      ExprKind::AssignOp(_, lhs, rhs) => {
+      ExprKind::AssignOp(_, lhs, rhs) if !lhs.span.from_expansion() => {
         let lhs_ty = self.typeck_res.expr_ty_adjusted(lhs);
         log::debug!("Type of LHS: {:#?}", lhs_ty);
 
@@ -207,6 +607,9 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
           flow_context,
           is_lhs: true,
           expected: ExpectedPermissions::from_assignment(),
+          dynamic_write: None,
+          is_overwrite: false,
+          is_capture: false,
         };
 
         self.data.push(pb);
@@ -223,6 +626,9 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
           // We want the boundary to appear to the left of the deref.
           location: expr.span.shrink_to_lo(),
           expected: self.get_adjusted_permissions(expr),
+          dynamic_write: None,
+          is_overwrite: false,
+          is_capture: false,
         };
         self.data.push(pb);
       }
@@ -242,6 +648,9 @@ impl<'tcx> Visitor<'tcx> for HirExprScraper<'tcx> {
           is_lhs: false,
           location: span.shrink_to_lo(),
           expected: self.get_adjusted_permissions(expr),
+          dynamic_write: None,
+          is_overwrite: false,
+          is_capture: false,
         };
         self.data.push(pb);
       }
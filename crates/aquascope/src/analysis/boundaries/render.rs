@@ -0,0 +1,268 @@
+//! Rendering of permission violations as compiler-style diagnostics.
+//!
+//! The web frontend draws boundaries over the source code, this module
+//! instead prints each violating [`PermissionsBoundary`] the way `rustc`
+//! prints an error, with the relevant source lines annotated:
+//!
+//! ```text
+//! error: `s` needs W but has R
+//!  --> src/main.rs:4:4
+//!   |
+//! 3 |   let r = &s;
+//!   |           -- loan L0 created here
+//! 4 |   s.push_str("!");
+//!   |    ^ `s` needs W here
+//! 5 |   println!("{r}");
+//!   |              - loan L0 still live here
+//! ```
+
+use std::fmt::Write;
+
+use rustc_utils::source_map::range::{CharPos, CharRange};
+use serde::{Deserialize, Serialize};
+
+use super::PermissionsBoundary;
+use crate::analysis::{AnalysisOutput, permissions::Permissions};
+
+/// The format in which the results of an analysis are printed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Hash)]
+pub enum OutputFormat {
+  /// The serialized [`AnalysisOutput`], as read by the frontend.
+  Json,
+
+  /// Permission violations as compiler-style diagnostics.
+  Text,
+}
+
+impl std::str::FromStr for OutputFormat {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "Json" => Ok(Self::Json),
+      "Text" => Ok(Self::Text),
+      _ => Err(format!("Could not parse: {s}")),
+    }
+  }
+}
+
+/// A message attached to a range of the source.
+struct Label {
+  range: CharRange,
+  message: String,
+  is_primary: bool,
+}
+
+/// Render each violating boundary in `output` as a diagnostic over
+/// the `source` of the file `file_name`.
+pub fn render_violations(
+  file_name: &str,
+  source: &str,
+  output: &AnalysisOutput,
+) -> String {
+  let lines = source.lines().collect::<Vec<_>>();
+  output
+    .boundaries
+    .iter()
+    .filter(|boundary| boundary.is_violation())
+    .map(|boundary| render_violation(file_name, &lines, output, boundary))
+    .collect()
+}
+
+fn render_violation(
+  file_name: &str,
+  lines: &[&str],
+  output: &AnalysisOutput,
+  boundary: &PermissionsBoundary,
+) -> String {
+  let path = boundary.path_name.as_deref().unwrap_or("path");
+  let expected = boundary.expected;
+  let actual = boundary.actual;
+  let missing = Permissions {
+    read: expected.read && !actual.read,
+    write: expected.write && !actual.write,
+    drop: expected.drop && !actual.drop,
+  };
+
+  let mut labels = vec![Label {
+    range: CharRange {
+      start: boundary.location,
+      end: boundary.location,
+      ..output.body_range
+    },
+    message: format!("`{path}` needs {missing:?} here"),
+    is_primary: true,
+  }];
+  let mut notes = Vec::new();
+
+  let data = &boundary.data;
+  if let Some(explanation) = &boundary.explanation {
+    let loan = *explanation.loan;
+    labels.push(Label {
+      range: explanation.issued_at,
+      message: format!("loan L{loan} created here"),
+      is_primary: false,
+    });
+    labels.push(Label {
+      range: explanation.used_at,
+      message: format!("loan L{loan} still live here"),
+      is_primary: false,
+    });
+  }
+
+  if let Some(range) =
+    data.path_moved.and_then(|m| output.move_points.0.get(&m))
+  {
+    labels.push(Label {
+      range: *range,
+      message: format!("`{path}` moved here"),
+      is_primary: false,
+    });
+  } else if data.path_uninitialized {
+    notes.push(format!("`{path}` is not initialized"));
+  }
+
+  if missing.write && !data.type_writeable {
+    notes.push(format!("`{path}` is not declared as mutable"));
+  }
+
+  if missing.drop && !data.type_droppable {
+    notes.push(format!("`{path}` does not own its data"));
+  }
+
+  let mut out = String::new();
+  let CharPos { line, column } = boundary.location;
+  writeln!(out, "error: `{path}` needs {missing:?} but has {actual:?}")
+    .unwrap();
+
+  let gutter = labels
+    .iter()
+    .map(|label| (label.range.start.line + 1).to_string().len())
+    .max()
+    .unwrap_or(1);
+  let pad = " ".repeat(gutter);
+  writeln!(out, "{pad}--> {file_name}:{}:{}", line + 1, column + 1).unwrap();
+  writeln!(out, "{pad} |").unwrap();
+
+  labels.sort_by_key(|label| {
+    (
+      label.range.start.line,
+      label.range.start.column,
+      !label.is_primary,
+    )
+  });
+  let mut previous_line = None;
+  for label in &labels {
+    let start = label.range.start;
+    if previous_line != Some(start.line) {
+      if previous_line.is_some_and(|prev| prev + 1 < start.line) {
+        writeln!(out, "{pad}...").unwrap();
+      }
+      let text = lines.get(start.line).copied().unwrap_or_default();
+      writeln!(out, "{:>gutter$} | {text}", start.line + 1).unwrap();
+      previous_line = Some(start.line);
+    }
+
+    // Ranges spanning multiple lines are underlined to the end of the first.
+    let end_column = if label.range.end.line == start.line {
+      label.range.end.column
+    } else {
+      lines.get(start.line).map_or(0, |text| text.chars().count())
+    };
+    let marker = if label.is_primary { "^" } else { "-" };
+    let width = end_column.saturating_sub(start.column).max(1);
+    writeln!(
+      out,
+      "{pad} | {}{} {}",
+      " ".repeat(start.column),
+      marker.repeat(width),
+      label.message
+    )
+    .unwrap();
+  }
+
+  if !notes.is_empty() || !boundary.suggestions.is_empty() {
+    writeln!(out, "{pad} |").unwrap();
+  }
+  for note in &notes {
+    writeln!(out, "{pad} = note: {note}").unwrap();
+  }
+  for suggestion in &boundary.suggestions {
+    writeln!(out, "{pad} = help: {}", suggestion.message).unwrap();
+  }
+  writeln!(out).unwrap();
+
+  out
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{analysis::AquascopeAnalysis, test_utils as tu};
+
+  fn render(code: &'static str) -> String {
+    let mut rendered = String::new();
+    tu::compile_normal(code, |tcx| {
+      tu::for_each_body(tcx, |body_id, _| {
+        let output = AquascopeAnalysis::run(tcx, body_id, true).unwrap();
+        rendered.push_str(&render_violations("main.rs", code, &output));
+      });
+    });
+    rendered
+  }
+
+  #[test]
+  fn render_loan_violation() {
+    let rendered = render(
+      r#"
+fn main() {
+  let mut s = String::new();
+  let r = &s;
+  s.push_str("!");
+  println!("{r}");
+}
+"#,
+    );
+
+    let lines = rendered.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "error: `s` needs W but has R", "{rendered}");
+    assert_eq!(lines[1], " --> main.rs:5:4");
+    assert!(lines.contains(&r#"4 |   let r = &s;"#));
+    assert!(lines.contains(&r#"5 |   s.push_str("!");"#));
+    assert!(lines.contains(&r#"6 |   println!("{r}");"#));
+    assert!(lines.iter().any(|line| line.ends_with("created here")));
+    assert!(lines.iter().any(|line| line.ends_with("still live here")));
+    assert!(lines.contains(&"  |    ^ `s` needs W here"));
+  }
+
+  #[test]
+  fn render_immutable_path() {
+    let rendered = render(
+      r"
+fn main() {
+  let v = Vec::new();
+  v.push(1);
+}
+",
+    );
+
+    assert!(
+      rendered.starts_with("error: `v` needs W but has RD"),
+      "{rendered}"
+    );
+    assert!(rendered.contains("= note: `v` is not declared as mutable"));
+  }
+
+  #[test]
+  fn render_nothing_without_violations() {
+    let rendered = render(
+      r"
+fn main() {
+  let mut v = Vec::new();
+  v.push(1);
+}
+",
+    );
+
+    assert_eq!(rendered, "");
+  }
+}
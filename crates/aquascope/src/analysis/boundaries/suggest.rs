@@ -0,0 +1,546 @@
+//! Source edits which may fix a permission violation.
+//!
+//! The [`PermissionsData`] at a violating boundary records which
+//! refinement removed an expected permission, and each has a
+//! conventional fix:
+//!
+//! - A path declared without `mut` can be made mutable.
+//! - A moved path can be cloned, or borrowed, at the move site.
+//! - A path moved out of a reference can be cloned instead.
+//! - A path refined by a live loan can be used after the last
+//!   use of the loan, by moving the statement using it.
+//!
+//! The edits are only candidates, applying one can well introduce new
+//! errors. The driver checks each by applying it with [`apply_edits`],
+//! re-running the analysis on the edited source, and looking for the
+//! violation at its [`edited_position`].
+
+use either::Either;
+use fluid_let::fluid_let;
+use rustc_hir::{BindingMode, ByRef, HirId, Node};
+use rustc_middle::{
+  mir::{
+    BindingForm, LocalInfo, Mutability, Operand, Place, ProjectionElem, Rvalue,
+    Statement, StatementKind, Terminator, TerminatorKind, VarBindingForm,
+  },
+  ty::{Ty, TyCtxt, TypingEnv},
+};
+use rustc_span::Span;
+use rustc_trait_selection::infer::{InferCtxtExt, TyCtxtInferExt};
+use rustc_utils::{
+  BodyExt, PlaceExt, SpanExt,
+  source_map::range::{CharPos, CharRange},
+};
+use serde::Serialize;
+use ts_rs::TS;
+
+use super::explain::LoanExplanation;
+use crate::analysis::{
+  AquascopeAnalysis,
+  permissions::{Move, Permissions, PermissionsData},
+};
+
+fluid_let!(pub static ENABLE_SUGGESTIONS: bool);
+pub const ENABLE_SUGGESTIONS_DEFAULT: bool = false;
+
+/// Replace the source in `range` with `replacement`.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct SourceEdit {
+  pub range: CharRange,
+  pub replacement: String,
+}
+
+/// A fix for a violation, made of non-overlapping source edits.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct FixSuggestion {
+  pub message: String,
+  pub edits: Vec<SourceEdit>,
+}
+
+/// Apply the `edits`, whose ranges refer to the original `source`.
+pub fn apply_edits(source: &str, edits: &[SourceEdit]) -> String {
+  let line_starts = std::iter::once(0)
+    .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+    .collect::<Vec<_>>();
+
+  let offset = |pos: CharPos| {
+    let Some(&start) = line_starts.get(pos.line) else {
+      return source.len();
+    };
+    source[start ..]
+      .char_indices()
+      .nth(pos.column)
+      .map_or(source.len(), |(i, _)| start + i)
+  };
+
+  // Edits are applied back to front, leaving the offsets of
+  // the ones before them unchanged.
+  let mut edits = edits.iter().collect::<Vec<_>>();
+  edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.column));
+
+  let mut edited = source.to_string();
+  for edit in edits.into_iter().rev() {
+    let range = offset(edit.range.start) .. offset(edit.range.end);
+    edited.replace_range(range, &edit.replacement);
+  }
+
+  edited
+}
+
+/// The position of `pos` in the source once the `edits` are applied,
+/// or `None` if an edit replaces the source at `pos`.
+pub fn edited_position(pos: CharPos, edits: &[SourceEdit]) -> Option<CharPos> {
+  let key = |pos: CharPos| (pos.line, pos.column);
+
+  // As in [`apply_edits`], the edits after `pos` leave it unchanged,
+  // and the ones before are applied back to front.
+  let mut edits = edits.iter().collect::<Vec<_>>();
+  edits.sort_by_key(|edit| key(edit.range.start));
+
+  let mut pos = pos;
+  for edit in edits.into_iter().rev() {
+    let CharRange { start, end, .. } = edit.range;
+    if key(pos) < key(end) {
+      if key(start) <= key(pos) {
+        return None;
+      }
+      continue;
+    }
+
+    let lines = edit.replacement.split('\n').collect::<Vec<_>>();
+    let last = lines.last().map_or(0, |line| line.chars().count());
+    let new_end = if lines.len() > 1 {
+      CharPos {
+        line: start.line + lines.len() - 1,
+        column: last,
+      }
+    } else {
+      CharPos {
+        line: start.line,
+        column: start.column + last,
+      }
+    };
+
+    pos = if pos.line == end.line {
+      CharPos {
+        line: new_end.line,
+        column: new_end.column + (pos.column - end.column),
+      }
+    } else {
+      CharPos {
+        line: pos.line + new_end.line - end.line,
+        ..pos
+      }
+    };
+  }
+
+  Some(pos)
+}
+
+/// Candidate fixes for the permissions missing from `actual` at the
+/// boundary of `place`, used by the HIR node `hir_id`.
+pub(super) fn suggest_fixes<'tcx>(
+  analysis: &AquascopeAnalysis<'tcx>,
+  hir_id: HirId,
+  place: Place<'tcx>,
+  expected: Permissions,
+  actual: Permissions,
+  data: &PermissionsData,
+  explanation: Option<&LoanExplanation>,
+) -> Vec<FixSuggestion> {
+  let missing = Permissions {
+    read: expected.read && !actual.read,
+    write: expected.write && !actual.write,
+    drop: expected.drop && !actual.drop,
+  };
+
+  if data.unchecked || !(missing.read || missing.write || missing.drop) {
+    return Vec::default();
+  }
+
+  let mut suggestions = Vec::default();
+
+  if missing.write && !data.type_writeable {
+    suggestions.extend(suggest_mut(analysis, place));
+  }
+
+  if let Some(mv) = data.path_moved {
+    let mv = Move::from_u32(mv.0);
+    suggestions.extend(suggest_instead_of_move(analysis, mv));
+  } else if missing.drop && !data.type_droppable {
+    let ctxt = &analysis.permissions;
+    let ty = place.ty(&ctxt.body_with_facts.body, ctxt.tcx).ty;
+    let span = ctxt.tcx.hir_span(hir_id);
+    suggestions.extend(suggest_clone(analysis, span, ty));
+  }
+
+  if let Some(explanation) = explanation {
+    suggestions.extend(suggest_use_after_loan(analysis, hir_id, explanation));
+  }
+
+  suggestions
+}
+
+fn edit(
+  analysis: &AquascopeAnalysis,
+  span: Span,
+  replacement: String,
+) -> SourceEdit {
+  SourceEdit {
+    range: analysis.span_to_range(span),
+    replacement,
+  }
+}
+
+fn implements_clone<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  typing_env: TypingEnv<'tcx>,
+  ty: Ty<'tcx>,
+) -> bool {
+  let Some(clone_trait) = tcx.lang_items().clone_trait() else {
+    return false;
+  };
+  let ty = tcx.erase_and_anonymize_regions(ty);
+  let (infcx, param_env) = tcx.infer_ctxt().build_with_typing_env(typing_env);
+  infcx
+    .type_implements_trait(clone_trait, [ty], param_env)
+    .must_apply_modulo_regions()
+}
+
+/// Add `mut` to the binding of a local which isn't declared mutable.
+fn suggest_mut<'tcx>(
+  analysis: &AquascopeAnalysis<'tcx>,
+  place: Place<'tcx>,
+) -> Option<FixSuggestion> {
+  let ctxt = &analysis.permissions;
+  let body = &ctxt.body_with_facts.body;
+
+  // Writing through a shared reference isn't fixed by the binding.
+  if place
+    .projection
+    .iter()
+    .any(|elem| matches!(elem, ProjectionElem::Deref))
+  {
+    return None;
+  }
+
+  let decl = &body.local_decls[place.local];
+  let LocalInfo::User(BindingForm::Var(VarBindingForm {
+    binding_mode: BindingMode(ByRef::No, Mutability::Not),
+    ..
+  })) = decl.local_info()
+  else {
+    return None;
+  };
+
+  let span = decl.source_info.span;
+  if span.from_expansion() {
+    return None;
+  }
+
+  let name = place.to_string(ctxt.tcx, body)?;
+  Some(FixSuggestion {
+    message: format!("make `{name}` mutable"),
+    edits: vec![edit(analysis, span.shrink_to_lo(), "mut ".to_string())],
+  })
+}
+
+/// Clone the value of the expression at `span`, if its type allows.
+fn suggest_clone<'tcx>(
+  analysis: &AquascopeAnalysis<'tcx>,
+  span: Span,
+  ty: Ty<'tcx>,
+) -> Option<FixSuggestion> {
+  let ctxt = &analysis.permissions;
+  let tcx = ctxt.tcx;
+  if span.from_expansion() || !implements_clone(tcx, ctxt.typing_env, ty) {
+    return None;
+  }
+
+  let snippet = tcx.sess.source_map().span_to_snippet(span).ok()?;
+  let replacement = if snippet.starts_with('*') {
+    format!("({snippet}).clone()")
+  } else {
+    format!("{snippet}.clone()")
+  };
+
+  Some(FixSuggestion {
+    message: format!("clone `{snippet}`"),
+    edits: vec![edit(analysis, span, replacement)],
+  })
+}
+
+/// Clone, or borrow, the path at the site of the move `mv`.
+fn suggest_instead_of_move<'tcx>(
+  analysis: &AquascopeAnalysis<'tcx>,
+  mv: Move,
+) -> Vec<FixSuggestion> {
+  let ctxt = &analysis.permissions;
+  let body = &ctxt.body_with_facts.body;
+  let move_out = &ctxt.move_data.moves[mv];
+  let moved = ctxt.move_data.move_paths[move_out.path].place;
+
+  let is_moved =
+    |op: &Operand<'tcx>| matches!(op, Operand::Move(p) if *p == moved);
+  let site = match body.stmt_at(move_out.source) {
+    Either::Left(Statement {
+      kind: StatementKind::Assign(box (_, Rvalue::Use(op))),
+      source_info,
+      ..
+    }) if is_moved(op) => Some(source_info.span),
+    Either::Right(Terminator {
+      kind: TerminatorKind::Call { args, .. },
+      ..
+    }) => args
+      .iter()
+      .find(|arg| is_moved(&arg.node))
+      .map(|arg| arg.span),
+    _ => None,
+  };
+
+  let Some(span) = site.and_then(|span| span.as_local(body.span)) else {
+    return Vec::default();
+  };
+
+  let ty = moved.ty(body, ctxt.tcx).ty;
+  let borrow =
+    ctxt
+      .tcx
+      .sess
+      .source_map()
+      .span_to_snippet(span)
+      .ok()
+      .map(|snippet| FixSuggestion {
+        message: format!("borrow `{snippet}` instead of moving it"),
+        edits: vec![edit(analysis, span, format!("&{snippet}"))],
+      });
+
+  suggest_clone(analysis, span, ty)
+    .into_iter()
+    .chain(borrow)
+    .collect()
+}
+
+/// Move the statement of the boundary at `hir_id` after the statement
+/// in the same block which last uses the refining loan.
+fn suggest_use_after_loan(
+  analysis: &AquascopeAnalysis,
+  hir_id: HirId,
+  explanation: &LoanExplanation,
+) -> Option<FixSuggestion> {
+  let ctxt = &analysis.permissions;
+  let tcx = ctxt.tcx;
+  let body = &ctxt.body_with_facts.body;
+
+  let stmt_id = std::iter::once(hir_id)
+    .chain(tcx.hir_parent_id_iter(hir_id))
+    .find(|&id| matches!(tcx.hir_node(id), Node::Stmt(_)))?;
+  let Node::Block(block) = tcx.parent_hir_node(stmt_id) else {
+    return None;
+  };
+  let index_of = |id: HirId| {
+    std::iter::once(id)
+      .chain(tcx.hir_parent_id_iter(id))
+      .find_map(|id| block.stmts.iter().position(|stmt| stmt.hir_id == id))
+  };
+
+  let stmt_index = index_of(stmt_id)?;
+  let use_index = index_of(body.location_to_hir_id(explanation.use_location))?;
+  if use_index <= stmt_index {
+    return None;
+  }
+
+  let span_of = |index: usize| block.stmts[index].span.as_local(body.span);
+  let stmt_span = span_of(stmt_index)?;
+  let next_span = span_of(stmt_index + 1)?;
+  let use_span = span_of(use_index)?;
+
+  let source_map = tcx.sess.source_map();
+  let snippet = source_map.span_to_snippet(stmt_span).ok()?;
+  let preceding = source_map.span_to_prev_source(stmt_span).ok()?;
+  let indent = preceding.rsplit('\n').next().unwrap_or_default();
+  if !indent.chars().all(char::is_whitespace) {
+    return None;
+  }
+
+  let loan = *explanation.loan;
+  Some(FixSuggestion {
+    message: format!("use this after the last use of loan L{loan}"),
+    edits: vec![
+      edit(analysis, stmt_span.with_hi(next_span.lo()), String::new()),
+      edit(
+        analysis,
+        use_span.shrink_to_hi(),
+        format!("\n{indent}{snippet}"),
+      ),
+    ],
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use fluid_let::fluid_set;
+  use rustc_utils::test_utils::DUMMY_FILE;
+
+  use super::*;
+  use crate::{
+    analysis::boundaries::compute_permission_boundaries, test_utils as tu,
+  };
+
+  fn pos(line: usize, column: usize) -> CharPos {
+    CharPos { line, column }
+  }
+
+  fn source_edit(
+    start: (usize, usize),
+    end: (usize, usize),
+    replacement: &str,
+  ) -> SourceEdit {
+    SourceEdit {
+      range: DUMMY_FILE.with(|filename| CharRange {
+        start: pos(start.0, start.1),
+        end: pos(end.0, end.1),
+        filename: *filename,
+      }),
+      replacement: replacement.to_string(),
+    }
+  }
+
+  /// The suggestions for each violation in `code`, as their message
+  /// and the source they edit `code` into.
+  fn suggestions(code: &str) -> Vec<(String, String)> {
+    let mut suggestions = Vec::default();
+    tu::compile_normal(code, |tcx| {
+      tu::for_each_body(tcx, |body_id, _| {
+        fluid_set!(ENABLE_SUGGESTIONS, true);
+        let analysis = AquascopeAnalysis::new(tcx, body_id);
+        let boundaries = compute_permission_boundaries(&analysis).unwrap();
+        suggestions.extend(
+          boundaries
+            .into_iter()
+            .flat_map(|boundary| boundary.suggestions)
+            .map(|fix| (fix.message, apply_edits(code, &fix.edits))),
+        );
+      });
+    });
+    suggestions
+  }
+
+  #[test]
+  fn apply_edits_back_to_front() {
+    let source = "let x = a;\nlet y = x;\n";
+    let edits = [
+      source_edit((1, 8), (1, 9), "&x"),
+      source_edit((0, 4), (0, 4), "mut "),
+      source_edit((0, 8), (0, 9), "a.clone()"),
+    ];
+    assert_eq!(
+      apply_edits(source, &edits),
+      "let mut x = a.clone();\nlet y = &x;\n"
+    );
+  }
+
+  #[test]
+  fn apply_edits_across_lines() {
+    let source = "  a();\n  b();\n  c();\n}";
+    let edits = [
+      source_edit((0, 2), (1, 2), ""),
+      source_edit((2, 6), (2, 6), "\n  a();"),
+    ];
+    assert_eq!(apply_edits(source, &edits), "  b();\n  c();\n  a();\n}");
+  }
+
+  #[test]
+  fn apply_edits_counts_chars() {
+    let source = "let é = \"ü\"; x";
+    let edits = [source_edit((0, 13), (0, 14), "y")];
+    assert_eq!(apply_edits(source, &edits), "let é = \"ü\"; y");
+  }
+
+  #[test]
+  fn edited_positions() {
+    let edits = [
+      source_edit((0, 4), (0, 4), "mut "),
+      source_edit((1, 2), (2, 2), ""),
+      source_edit((3, 6), (3, 6), "\n  a();"),
+    ];
+    assert_eq!(edited_position(pos(0, 8), &edits), Some(pos(0, 12)));
+    assert_eq!(edited_position(pos(1, 0), &edits), Some(pos(1, 0)));
+    assert_eq!(edited_position(pos(1, 4), &edits), None);
+    assert_eq!(edited_position(pos(2, 4), &edits), Some(pos(1, 4)));
+    assert_eq!(edited_position(pos(3, 7), &edits), Some(pos(3, 7)));
+    assert_eq!(edited_position(pos(4, 0), &edits), Some(pos(4, 0)));
+  }
+
+  #[test]
+  fn suggest_mut_on_immutable_binding() {
+    let code = r"
+fn push() {
+  let v = Vec::new();
+  v.push(1);
+}
+";
+    let fixed = code.replace("let v", "let mut v");
+    assert_eq!(suggestions(code), vec![(
+      "make `v` mutable".to_string(),
+      fixed
+    )]);
+  }
+
+  #[test]
+  fn suggest_clone_out_of_reference() {
+    let code = r"
+fn take(s: &String) -> String {
+  *s
+}
+";
+    let fixed = code.replace("*s", "(*s).clone()");
+    assert_eq!(suggestions(code), vec![("clone `*s`".to_string(), fixed)]);
+  }
+
+  #[test]
+  fn suggest_instead_of_move() {
+    let code = r"
+fn moved(s: String) {
+  let t = s;
+  drop(s);
+}
+";
+    assert_eq!(suggestions(code), vec![
+      (
+        "clone `s`".to_string(),
+        code.replace("= s;", "= s.clone();")
+      ),
+      (
+        "borrow `s` instead of moving it".to_string(),
+        code.replace("= s;", "= &s;")
+      ),
+    ]);
+  }
+
+  #[test]
+  fn suggest_use_after_loan() {
+    let code = r"
+fn push_while_borrowed() {
+  let mut v = vec![1];
+  let first = &v[0];
+  v.push(2);
+  let n = *first;
+}
+";
+    let fixed = code.replace(
+      "  v.push(2);\n  let n = *first;\n",
+      "  let n = *first;\n  v.push(2);\n",
+    );
+    let suggestions = suggestions(code);
+    assert_eq!(suggestions.len(), 1, "{suggestions:#?}");
+    assert!(
+      suggestions[0]
+        .0
+        .starts_with("use this after the last use of loan")
+    );
+    assert_eq!(suggestions[0].1, fixed);
+  }
+}
@@ -58,13 +58,21 @@ impl<'tcx> CleanedBody<'tcx> {
   }
 
   pub fn blocks(&self) -> impl Iterator<Item = BasicBlock> + use<'tcx, '_> {
+    // Blocks only reachable through removed edges (e.g., coroutine drops)
+    // are not part of the cleaned graph.
+    let mut reachable =
+      iterate::DepthFirstSearch::new(self).with_start_node(self.start_node());
+    reachable.complete_search();
     self
       .0
       .basic_blocks
       .reverse_postorder()
       .iter()
       .rev()
-      .filter(|bb| CleanedBody::keep_block(&self.0.basic_blocks[**bb]))
+      .filter(move |bb| {
+        CleanedBody::keep_block(&self.0.basic_blocks[**bb])
+          && reachable.visited(**bb)
+      })
       .copied()
   }
 
@@ -92,6 +100,20 @@ impl<'tcx> CleanedBody<'tcx> {
 
     imaginary_target == target
   }
+
+  /// Is `target` the start of the path taken when a suspended coroutine
+  /// is dropped? These paths end in `coroutine_drop`, a second exit
+  /// which is never taken by regular control-flow.
+  fn is_coroutine_drop_target(
+    from_data: &BasicBlockData,
+    target: BasicBlock,
+  ) -> bool {
+    match from_data.terminator().kind {
+      TerminatorKind::Yield { drop, .. }
+      | TerminatorKind::Drop { drop, .. } => drop == Some(target),
+      _ => false,
+    }
+  }
 }
 
 // -----------
@@ -113,11 +135,12 @@ impl StartNode for CleanedBody<'_> {
 
 impl Successors for CleanedBody<'_> {
   fn successors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> {
+    let node_data = &self.0.basic_blocks[node];
     <BasicBlocks as Successors>::successors(&self.0.basic_blocks, node)
       .filter(|bb| {
-        let from_data = &self.0.basic_blocks[*bb];
-        CleanedBody::keep_block(from_data)
-          && !CleanedBody::is_imaginary_target(from_data, *bb)
+        CleanedBody::keep_block(&self.0.basic_blocks[*bb])
+          && !CleanedBody::is_imaginary_target(node_data, *bb)
+          && !CleanedBody::is_coroutine_drop_target(node_data, *bb)
       })
       .collect::<SmallVec<[BasicBlock; 4]>>()
       .into_iter()
@@ -127,7 +150,12 @@ impl Successors for CleanedBody<'_> {
 impl Predecessors for CleanedBody<'_> {
   fn predecessors(&self, node: Self::Node) -> impl Iterator<Item = Self::Node> {
     <BasicBlocks as Predecessors>::predecessors(&self.0.basic_blocks, node)
-      .filter(|bb| CleanedBody::keep_block(&self.0.basic_blocks[*bb]))
+      .filter(|bb| {
+        let from_data = &self.0.basic_blocks[*bb];
+        CleanedBody::keep_block(from_data)
+          && !CleanedBody::is_imaginary_target(from_data, node)
+          && !CleanedBody::is_coroutine_drop_target(from_data, node)
+      })
       .collect::<SmallVec<[BasicBlock; 4]>>()
       .into_iter()
   }
@@ -2,8 +2,9 @@
 pub(crate) mod body_graph;
 #[allow(dead_code)]
 pub(crate) mod mir_locations;
+pub(crate) mod place_resolver;
 pub(crate) mod post_dominators;
-// pub(crate) mod region_name;
+pub(crate) mod region_name;
 
 use rustc_data_structures::{
   fx::{FxHashMap as HashMap, FxHashSet as HashSet},
@@ -18,7 +19,9 @@ use rustc_utils::BodyExt;
 
 use self::post_dominators::AllPostDominators;
 pub(crate) use self::{
-  body_graph::CleanedBody, mir_locations::MirOrderedLocations,
+  body_graph::CleanedBody,
+  mir_locations::MirOrderedLocations,
+  place_resolver::{Confidence, ResolvedPlace},
 };
 
 pub struct IRMapper<'tcx> {
@@ -245,6 +248,7 @@ impl<'tcx> IRMapper<'tcx> {
             self
               .post_dominators
               .is_postdominated_by(block, candidate_postdom)
+              && self.reaches_forward(block, candidate_postdom, entry_block)
           })
         })
         .copied()
@@ -270,7 +274,36 @@ impl<'tcx> IRMapper<'tcx> {
     })
   }
 
+  /// Returns true if `to` can be reached from `from` without following
+  /// a back-edge into a loop that starts before `entry`. Inside of a loop
+  /// all paths eventually leave it, so the exit of the loop post-dominates
+  /// blocks that never reach it on the same iteration.
+  fn reaches_forward(
+    &self,
+    from: BasicBlock,
+    to: BasicBlock,
+    entry: Option<BasicBlock>,
+  ) -> bool {
+    let mut visited = HashSet::default();
+    let mut stack = vec![from];
+    while let Some(block) = stack.pop() {
+      if block == to {
+        return true;
+      }
+      if visited.insert(block) {
+        stack.extend(self.cleaned_graph.successors(block).filter(|&succ| {
+          !self.dominates(succ, block)
+            || entry.is_some_and(|entry| self.dominates(entry, succ))
+        }));
+      }
+    }
+    false
+  }
+
   fn is_block_unreachable(&self, block: BasicBlock) -> bool {
+    if !self.dominators.is_reachable(block) {
+      return true;
+    }
     let block_data = &self.body.basic_blocks[block];
     let term = block_data.terminator();
     matches!(term.kind, mir::TerminatorKind::Unreachable)
@@ -309,7 +342,11 @@ impl<'tcx> MirVisitor<'tcx> for IRMapper<'tcx> {
   ) {
     match self.gather_mode {
       GatherMode::All => self.super_basic_block_data(block, data),
-      GatherMode::IgnoreCleanup if !data.is_cleanup => {
+      // Blocks outside the cleaned graph, e.g., those dropping a
+      // suspended coroutine, are ignored alongside the cleanup blocks.
+      GatherMode::IgnoreCleanup
+        if !data.is_cleanup && self.dominators.is_reachable(block) =>
+      {
         self.super_basic_block_data(block, data)
       }
       GatherMode::IgnoreCleanup => {
@@ -0,0 +1,639 @@
+//! Structural resolution of HIR place expressions to MIR places.
+//!
+//! A place expression such as `x.0[i]` or `*r` is lowered to a MIR
+//! [`Place`] of the same shape, a base local followed by one projection
+//! per field access, index, or dereference:
+//!
+//! ```text
+//! x.0[i]  ~>  _1.0[_3]
+//! *r      ~>  (*_2)
+//! ```
+//!
+//! Given the places used at the MIR locations of a HIR node, the resolver
+//! picks the one matching the shape of the expression. The base local is
+//! found through the variable debug info, which records the span of the
+//! identifier a variable is bound to. Captured variables have a
+//! debug info entry of their own, so these resolve to the projection
+//! of the closure environment.
+//!
+//! A binding pattern is resolved to the place it matches, the place of
+//! the scrutinee followed by a projection per enclosing pattern:
+//!
+//! ```text
+//! let (a, Some(b)) = t;  ~>  b: ((_1.1) as Some).0
+//! ```
+//!
+//! A borrowed scrutinee is first stored in a temporary, so its
+//! bindings are resolved to places behind that temporary:
+//!
+//! ```text
+//! match &opt { Some(s) => .. }  ~>  s: ((*_2) as Some).0
+//! ```
+//!
+//! Overloaded operators, e.g. indexing a `Vec`, are lowered to a method
+//! call and a dereference of its result. The shape of an expression is
+//! only known up to the first such operator.
+
+use rustc_abi::FieldIdx;
+use rustc_hir::{self as hir, ExprKind, HirId, Node, PatKind, QPath, def::Res};
+use rustc_middle::{
+  mir::{
+    Local, Location, Place, PlaceElem, ProjectionElem, Rvalue, StatementKind,
+    VarDebugInfoContents,
+  },
+  ty::{
+    self, Ty, TypeckResults,
+    adjustment::{Adjust, DerefAdjustKind, PatAdjust},
+  },
+};
+
+use super::{GatherDepth, IRMapper};
+
+/// How certain the resolver is that a place is the one used by an expression.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+  /// The place was not structurally matched. It was picked by
+  /// the similarity of its name to the expression source.
+  Fallback,
+
+  /// The base local matches, but the projections only do so partially.
+  BaseLocal,
+
+  /// The base local and all projections match, but the type of the
+  /// place differs from that of the expression.
+  Shape,
+
+  /// The base local, projections, and type of the place all match.
+  Exact,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ResolvedPlace<'tcx> {
+  pub location: Location,
+  pub place: Place<'tcx>,
+  pub confidence: Confidence,
+}
+
+/// A projection as it appears in a HIR place expression.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum HirProjection {
+  Deref,
+  Field(FieldIdx),
+  Index,
+}
+
+impl HirProjection {
+  fn matches<'tcx>(self, elem: PlaceElem<'tcx>) -> bool {
+    match (self, elem) {
+      (HirProjection::Field(f1), ProjectionElem::Field(f2, _)) => f1 == f2,
+      (HirProjection::Deref, ProjectionElem::Deref)
+      | (
+        HirProjection::Index,
+        ProjectionElem::Index(_)
+        | ProjectionElem::ConstantIndex { .. }
+        | ProjectionElem::Subslice { .. },
+      ) => true,
+      _ => false,
+    }
+  }
+}
+
+/// The shape of a HIR place expression.
+#[derive(Debug)]
+struct HirPlace<'tcx> {
+  /// The MIR place of the base variable.
+  base: Place<'tcx>,
+  projections: Vec<HirProjection>,
+  /// Are the projections complete? Shapes stop at the first
+  /// overloaded operator.
+  complete: bool,
+  ty: Ty<'tcx>,
+}
+
+impl HirPlace<'_> {
+  fn push(&mut self, projection: HirProjection) {
+    if self.complete {
+      self.projections.push(projection);
+    }
+  }
+}
+
+/// Push the projections from the pattern `pat` to its subpattern `target`.
+///
+/// Returns whether `target` was found within `pat`.
+fn pat_projections(
+  typeck_res: &TypeckResults,
+  pat: &hir::Pat,
+  target: HirId,
+  place: &mut HirPlace,
+) -> bool {
+  let (projections, complete) = (place.projections.len(), place.complete);
+
+  // Default binding modes implicitly dereference the scrutinee.
+  if let Some(adjustments) = typeck_res.pat_adjustments().get(pat.hir_id) {
+    for adjustment in adjustments {
+      match adjustment.kind {
+        PatAdjust::BuiltinDeref => place.push(HirProjection::Deref),
+        PatAdjust::OverloadedDeref | PatAdjust::PinDeref => {
+          place.complete = false;
+        }
+      }
+    }
+  }
+
+  // Fields of a tuple (struct) pattern, accounting for a `..` in the middle.
+  let mut subpatterns =
+    |pats: &[hir::Pat], dotdot: hir::DotDotPos, fields: usize| {
+      pats.iter().enumerate().any(|(i, sub)| {
+        let field = match dotdot.as_opt_usize() {
+          Some(pos) if i >= pos => i + fields - pats.len(),
+          _ => i,
+        };
+        let len = place.projections.len();
+        place.push(HirProjection::Field(FieldIdx::from_usize(field)));
+        pat_projections(typeck_res, sub, target, place) || {
+          place.projections.truncate(len);
+          false
+        }
+      })
+    };
+
+  let found = match pat.kind {
+    PatKind::Binding(..) if pat.hir_id == target => true,
+    PatKind::Binding(_, _, _, Some(sub)) | PatKind::Guard(sub, _) => {
+      pat_projections(typeck_res, sub, target, place)
+    }
+
+    PatKind::Tuple(pats, dotdot) => match typeck_res.pat_ty(pat).kind() {
+      ty::Tuple(tys) => subpatterns(pats, dotdot, tys.len()),
+      _ => false,
+    },
+
+    PatKind::TupleStruct(ref qpath, pats, dotdot) => {
+      match typeck_res.pat_ty(pat).ty_adt_def() {
+        Some(adt) => {
+          let res = typeck_res.qpath_res(qpath, pat.hir_id);
+          let fields = adt.variant_of_res(res).fields.len();
+          subpatterns(pats, dotdot, fields)
+        }
+        None => false,
+      }
+    }
+
+    PatKind::Struct(_, fields, _) => fields.iter().any(|field| {
+      let len = place.projections.len();
+      place.push(HirProjection::Field(typeck_res.field_index(field.hir_id)));
+      pat_projections(typeck_res, field.pat, target, place) || {
+        place.projections.truncate(len);
+        false
+      }
+    }),
+
+    PatKind::Box(sub) | PatKind::Ref(sub, ..) => {
+      place.push(HirProjection::Deref);
+      pat_projections(typeck_res, sub, target, place)
+    }
+
+    PatKind::Slice(before, mid, after) => {
+      before.iter().chain(mid).chain(after).any(|sub| {
+        let len = place.projections.len();
+        place.push(HirProjection::Index);
+        pat_projections(typeck_res, sub, target, place) || {
+          place.projections.truncate(len);
+          false
+        }
+      })
+    }
+
+    // Each alternative binds the variable to a different place.
+    PatKind::Or(pats) => pats
+      .iter()
+      .any(|sub| pat_projections(typeck_res, sub, target, place)),
+
+    PatKind::Deref(sub) => {
+      place.complete = false;
+      pat_projections(typeck_res, sub, target, place)
+    }
+
+    _ => false,
+  };
+
+  if !found {
+    place.projections.truncate(projections);
+    place.complete = complete;
+  }
+
+  found
+}
+
+impl<'tcx> IRMapper<'tcx> {
+  /// The MIR place of the variable bound by the pattern `binding`.
+  fn binding_place(&self, binding: HirId) -> Option<Place<'tcx>> {
+    let Node::Pat(hir::Pat {
+      kind: PatKind::Binding(_, _, ident, _),
+      span,
+      ..
+    }) = self.tcx.hir_node(binding)
+    else {
+      return None;
+    };
+
+    let mut places = self.body.var_debug_info.iter().filter_map(|info| {
+      let VarDebugInfoContents::Place(place) = info.value else {
+        return None;
+      };
+      (info.name == ident.name && span.contains(info.source_info.span))
+        .then_some(place)
+    });
+
+    // Bindings in or-patterns have multiple locals, in which
+    // case the binding doesn't name a single place.
+    let place = places.next()?;
+    places.next().is_none().then_some(place)
+  }
+
+  /// Compute the shape of `expr`, or `None` if it isn't a place expression.
+  fn expr_place(
+    &self,
+    typeck_res: &TypeckResults<'tcx>,
+    expr: &hir::Expr,
+  ) -> Option<HirPlace<'tcx>> {
+    let mut place = match expr.kind {
+      ExprKind::Path(QPath::Resolved(
+        _,
+        hir::Path {
+          res: Res::Local(base),
+          ..
+        },
+      )) => HirPlace {
+        base: self.binding_place(*base)?,
+        projections: Vec::default(),
+        complete: true,
+        ty: typeck_res.expr_ty(expr),
+      },
+
+      ExprKind::Field(inner, _) => {
+        let mut place = self.expr_place(typeck_res, inner)?;
+        match typeck_res.opt_field_index(expr.hir_id) {
+          Some(field) => place.push(HirProjection::Field(field)),
+          None => place.complete = false,
+        }
+        place.ty = typeck_res.expr_ty(expr);
+        place
+      }
+
+      ExprKind::Unary(hir::UnOp::Deref, inner) => {
+        let mut place = self.expr_place(typeck_res, inner)?;
+        if typeck_res.is_method_call(expr) {
+          place.complete = false;
+        }
+        place.push(HirProjection::Deref);
+        place.ty = typeck_res.expr_ty(expr);
+        place
+      }
+
+      ExprKind::Index(inner, _, _) => {
+        let mut place = self.expr_place(typeck_res, inner)?;
+        if typeck_res.is_method_call(expr) {
+          place.complete = false;
+        }
+        place.push(HirProjection::Index);
+        place.ty = typeck_res.expr_ty(expr);
+        place
+      }
+
+      _ => return None,
+    };
+
+    // Built-in auto-derefs, e.g. of a method receiver or the base of
+    // a field access, are projections of the place as well.
+    for adjustment in typeck_res.expr_adjustments(expr) {
+      match adjustment.kind {
+        Adjust::Deref(DerefAdjustKind::Builtin) => {
+          place.push(HirProjection::Deref);
+          place.ty = adjustment.target;
+        }
+        Adjust::Deref(
+          DerefAdjustKind::Overloaded(_) | DerefAdjustKind::Pin,
+        ) => {
+          place.complete = false;
+        }
+        _ => {}
+      }
+    }
+
+    Some(place)
+  }
+
+  /// Compute the shape of the place matched by a pattern against `scrutinee`.
+  ///
+  /// A borrowed scrutinee, e.g. `match &opt`, is matched through the
+  /// temporary holding the reference, which its pattern dereferences.
+  fn scrutinee_place(
+    &self,
+    typeck_res: &TypeckResults<'tcx>,
+    scrutinee: &hir::Expr,
+  ) -> Option<HirPlace<'tcx>> {
+    if !matches!(scrutinee.kind, ExprKind::AddrOf(..)) {
+      return self.expr_place(typeck_res, scrutinee);
+    }
+
+    let locations =
+      self.get_mir_locations(scrutinee.hir_id, GatherDepth::Outer)?;
+    let temporary = locations.values().find_map(|location| {
+      match &self.body.stmt_at(location).left()?.kind {
+        StatementKind::Assign(box (place, Rvalue::Ref(..))) => Some(*place),
+        _ => None,
+      }
+    })?;
+
+    Some(HirPlace {
+      base: temporary,
+      projections: Vec::default(),
+      complete: true,
+      ty: typeck_res.expr_ty(scrutinee),
+    })
+  }
+
+  /// Compute the shape of the place matched by the binding pattern `binding`.
+  ///
+  /// This is the place of the scrutinee, e.g. the initializer of a `let`
+  /// or the argument of a parameter, followed by the projections
+  /// of the patterns enclosing the binding.
+  fn binding_pat_place(
+    &self,
+    typeck_res: &TypeckResults<'tcx>,
+    binding: &hir::Pat,
+  ) -> Option<HirPlace<'tcx>> {
+    let tcx = self.tcx;
+    let mut root = binding;
+    let mut parents = tcx.hir_parent_iter(binding.hir_id);
+    let owner = loop {
+      match parents.next()?.1 {
+        Node::Pat(pat) => root = pat,
+        Node::PatField(_) => {}
+        node => break node,
+      }
+    };
+
+    let mut place = match owner {
+      Node::LetStmt(hir::LetStmt {
+        init: Some(scrutinee),
+        ..
+      })
+      | Node::Expr(hir::Expr {
+        kind:
+          ExprKind::Let(hir::LetExpr {
+            init: scrutinee, ..
+          }),
+        ..
+      }) => self.scrutinee_place(typeck_res, scrutinee)?,
+
+      Node::Arm(_) => {
+        let Node::Expr(hir::Expr {
+          kind: ExprKind::Match(scrutinee, ..),
+          ..
+        }) = parents.next()?.1
+        else {
+          return None;
+        };
+        self.scrutinee_place(typeck_res, scrutinee)?
+      }
+
+      Node::Param(param) => {
+        // Closures take their environment as the first argument.
+        let def_id = self.body.source.def_id().expect_local();
+        let params = tcx.hir_body_owned_by(def_id).params;
+        let index = params.iter().position(|p| p.hir_id == param.hir_id)?;
+        let local = self.body.arg_count - params.len() + index + 1;
+        HirPlace {
+          base: Place::from(Local::from_usize(local)),
+          projections: Vec::default(),
+          complete: true,
+          ty: typeck_res.pat_ty(root),
+        }
+      }
+
+      _ => return None,
+    };
+
+    if !pat_projections(typeck_res, root, binding.hir_id, &mut place) {
+      return None;
+    }
+    place.ty = typeck_res.pat_ty(binding);
+
+    Some(place)
+  }
+
+  /// Pick the place from `candidates` used by the place expression
+  /// or binding pattern `hir_id`.
+  ///
+  /// Candidates which don't share the base local of the expression are
+  /// never picked. Of the others, the one matching most projections wins,
+  /// ties are broken by candidate order.
+  pub fn resolve_place(
+    &self,
+    hir_id: HirId,
+    candidates: &[(Location, Place<'tcx>)],
+  ) -> Option<ResolvedPlace<'tcx>> {
+    let def_id = self.body.source.def_id().expect_local();
+    let typeck_res = self.tcx.typeck(def_id);
+
+    let hir_place = match self.tcx.hir_node(hir_id) {
+      Node::Expr(expr) => {
+        // The place of a borrow is the one borrowed.
+        let expr = match expr.kind {
+          ExprKind::AddrOf(_, _, inner) => inner,
+          _ => expr,
+        };
+        self.expr_place(typeck_res, expr)?
+      }
+      Node::Pat(pat) if matches!(pat.kind, PatKind::Binding(..)) => {
+        self.binding_pat_place(typeck_res, pat)?
+      }
+      _ => return None,
+    };
+    let base = hir_place.base;
+
+    log::debug!("Resolving {hir_place:?} with base {base:?}");
+
+    let tcx = self.tcx;
+    let expected_ty = tcx.erase_and_anonymize_regions(hir_place.ty);
+
+    let resolved = candidates
+      .iter()
+      .filter_map(|&(location, place)| {
+        if place.local != base.local {
+          return None;
+        }
+        let rest = place.projection.strip_prefix(&base.projection[..])?;
+
+        // Casts of opaque types and unsafe binders don't appear in the
+        // HIR, nor does the downcast to the variant of an enum pattern.
+        let rest = rest
+          .iter()
+          .filter(|elem| {
+            !matches!(
+              elem,
+              ProjectionElem::OpaqueCast(_)
+                | ProjectionElem::UnwrapUnsafeBinder(_)
+                | ProjectionElem::Downcast(..)
+            )
+          })
+          .collect::<Vec<_>>();
+
+        let matching = hir_place
+          .projections
+          .iter()
+          .zip(rest.iter())
+          .take_while(|(hir_elem, elem)| hir_elem.matches(***elem))
+          .count();
+
+        let confidence = if hir_place.complete
+          && matching == hir_place.projections.len()
+          && matching == rest.len()
+        {
+          let ty = place.ty(&self.body.local_decls, tcx).ty;
+          if tcx.erase_and_anonymize_regions(ty) == expected_ty {
+            Confidence::Exact
+          } else {
+            Confidence::Shape
+          }
+        } else {
+          Confidence::BaseLocal
+        };
+
+        Some((matching, ResolvedPlace {
+          location,
+          place,
+          confidence,
+        }))
+      })
+      // `max_by_key` picks the last maximum, but we want the first.
+      .rev()
+      .max_by_key(|(matching, resolved)| (resolved.confidence, *matching))
+      .map(|(_, resolved)| resolved);
+
+    log::debug!("Resolved place: {resolved:?}");
+
+    resolved
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use rustc_middle::mir::{Local, Operand, Rvalue, StatementKind};
+
+  use super::*;
+  use crate::{
+    analysis::{ir_mapper::GatherMode, scrape_hir::scrape_expr_data},
+    test_utils as tu,
+  };
+
+  /// Resolve the expression with the source `snippet` against all
+  /// places borrowed, copied, or moved in the body.
+  fn resolve_in(
+    code: &str,
+    snippet: &'static str,
+    check: impl Fn(Place, Confidence) + Sync,
+  ) {
+    tu::compile_normal(code, |tcx| {
+      tu::for_each_body(tcx, |body_id, wfacts| {
+        let body = &wfacts.body;
+        let mapper = IRMapper::new(tcx, body, GatherMode::IgnoreCleanup);
+        let source_map = tcx.sess.source_map();
+
+        let candidates = body
+          .basic_blocks
+          .iter_enumerated()
+          .flat_map(|(block, data)| {
+            data.statements.iter().enumerate().filter_map(
+                move |(statement_index, stmt)| {
+                  let StatementKind::Assign(box (_, rvalue)) = &stmt.kind
+                  else {
+                    return None;
+                  };
+                  let place = match rvalue {
+                    Rvalue::Ref(_, _, place)
+                    | Rvalue::Use(
+                      Operand::Copy(place) | Operand::Move(place),
+                    ) => *place,
+                    _ => return None,
+                  };
+                  Some((
+                    Location {
+                      block,
+                      statement_index,
+                    },
+                    place,
+                  ))
+                },
+              )
+          })
+          .collect::<Vec<_>>();
+
+        let ids = scrape_expr_data(tcx, body_id, |expr| {
+          (source_map.span_to_snippet(expr.span).ok()? == snippet)
+            .then_some(expr.hir_id)
+        });
+
+        let resolved = mapper
+          .resolve_place(ids[0], &candidates)
+          .expect("place was not resolved");
+        check(resolved.place, resolved.confidence);
+      })
+    });
+  }
+
+  #[test]
+  fn resolve_field_through_reference() {
+    resolve_in(
+      r#"
+struct P { x: (i32, i32) }
+fn foo(p: &mut P) -> i32 {
+  let r = &p.x.1;
+  *r
+}
+"#,
+      "&p.x.1",
+      |place, confidence| {
+        assert_eq!(place.local, Local::from_usize(1));
+        assert_eq!(place.projection.len(), 3);
+        assert_eq!(confidence, Confidence::Exact);
+      },
+    );
+  }
+
+  #[test]
+  fn resolve_array_index() {
+    resolve_in(
+      r#"
+fn foo(a: [i32; 2]) -> i32 {
+  a[0]
+}
+"#,
+      "a[0]",
+      |place, confidence| {
+        assert_eq!(place.local, Local::from_usize(1));
+        assert!(matches!(place.projection[..], [ProjectionElem::Index(_)]));
+        assert_eq!(confidence, Confidence::Exact);
+      },
+    );
+  }
+
+  #[test]
+  fn resolve_overloaded_index() {
+    resolve_in(
+      r#"
+fn foo(v: Vec<i32>) -> i32 {
+  v[0]
+}
+"#,
+      "v[0]",
+      |place, confidence| {
+        assert_eq!(place.local, Local::from_usize(1));
+        assert_eq!(confidence, Confidence::BaseLocal);
+      },
+    );
+  }
+}
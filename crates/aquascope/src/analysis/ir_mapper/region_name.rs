@@ -0,0 +1,325 @@
+//! Source-level names for MIR regions.
+//!
+//! The flow analysis (and Polonius) only ever talks about anonymous
+//! [`Origin`]s, e.g. `'?3`, which aren't very helpful to show to a person.
+//! Here we map origins back to something that appears in the source:
+//!
+//! - a lifetime parameter written in the signature, e.g. `'a`.
+//! - a lifetime elided in the signature, e.g. “the lifetime of `&self`”.
+//! - a borrow expression within the body, e.g. `&v[0]`.
+//!
+//! Signature regions are found by pairing the regions of the (renumbered)
+//! argument and return types of the MIR body with those of the user-written
+//! function signature. Regions without a direct name borrow one from an
+//! equivalent region, or from the local borrows that flow into them.
+
+use itertools::Itertools;
+use rustc_data_structures::fx::FxHashMap as HashMap;
+use rustc_hir::{
+  self as hir, AmbigArg, FnRetTy,
+  def::DefKind,
+  intravisit::{self, Visitor, VisitorExt},
+};
+use rustc_middle::ty::{self, Ty};
+use rustc_span::Span;
+use rustc_utils::{SpanExt, source_map::range::CharRange};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::analysis::permissions::{Origin, PermissionsCtxt, flow::SccIdx};
+
+/// A source-level name for an [`Origin`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "type")]
+pub enum RegionName {
+  /// A lifetime written by the user, e.g. `'a` or `'static`.
+  Named { name: String },
+
+  /// A lifetime elided in the signature, e.g. the lifetime of `&self`.
+  /// The `snippet` is the source of the type with the elided lifetime.
+  Elided { snippet: String, range: CharRange },
+
+  /// The region of a borrow expression within the body.
+  Borrow { range: CharRange },
+}
+
+pub struct RegionNames {
+  /// Regions written, or elided, in the function signature.
+  signature: HashMap<Origin, RegionName>,
+
+  /// Regions of the borrows within the body.
+  borrows: HashMap<Origin, (Span, RegionName)>,
+}
+
+impl RegionNames {
+  pub fn new(ctxt: &PermissionsCtxt) -> Self {
+    let signature = signature_region_names(ctxt);
+    let borrows = borrow_region_names(ctxt);
+
+    log::debug!("Signature region names: {signature:#?}");
+
+    RegionNames { signature, borrows }
+  }
+
+  /// The user-written lifetime for the abstract component `scc`, if any.
+  pub fn lifetime_of_scc(
+    &self,
+    ctxt: &PermissionsCtxt,
+    scc: SccIdx,
+  ) -> Option<&str> {
+    let region_flows = ctxt.region_flows();
+    self
+      .signature
+      .iter()
+      .filter(|(o, _)| region_flows.scc(**o) == scc)
+      .sorted_by_key(|(o, _)| **o)
+      .find_map(|(_, name)| match name {
+        RegionName::Named { name } => Some(name.as_str()),
+        _ => None,
+      })
+  }
+
+  /// Get the most readable name for `origin`.
+  ///
+  /// In order of preference this is the name of the origin itself
+  /// if it appears in the signature, that of an equivalent signature
+  /// region, the borrow creating the origin, the earliest local borrow
+  /// that flows into it, and lastly a signature region flowing into it,
+  /// e.g. for a reborrow of a parameter. The equivalent and flowing regions
+  /// are only considered when the region flows have been computed.
+  pub fn name_of(
+    &self,
+    ctxt: &PermissionsCtxt,
+    origin: Origin,
+  ) -> Option<RegionName> {
+    if let Some(name) = self.signature.get(&origin) {
+      return Some(name.clone());
+    }
+
+    let Some(region_flows) = ctxt.region_flows.as_ref() else {
+      return self.borrows.get(&origin).map(|(_, name)| name.clone());
+    };
+    let scc = region_flows.scc(origin);
+
+    let equivalent = self
+      .signature
+      .iter()
+      .filter(|(o, _)| region_flows.scc(**o) == scc)
+      .min_by_key(|(o, _)| **o)
+      .map(|(_, name)| name);
+
+    if let Some(name) = equivalent {
+      return Some(name.clone());
+    }
+
+    if let Some((_, name)) = self.borrows.get(&origin) {
+      return Some(name.clone());
+    }
+
+    let local_sources = region_flows.local_sources(origin);
+    let local = self
+      .borrows
+      .iter()
+      .filter(|(o, _)| local_sources.contains(&region_flows.scc(**o)))
+      .min_by_key(|(_, (span, _))| span.lo())
+      .map(|(_, (_, name))| name.clone());
+    if local.is_some() {
+      return local;
+    }
+
+    let abstract_sources = region_flows.abstract_sources(origin);
+    self
+      .signature
+      .iter()
+      .filter(|(o, _)| abstract_sources.contains(&region_flows.scc(**o)))
+      .min_by_key(|(o, _)| **o)
+      .map(|(_, name)| name.clone())
+  }
+}
+
+fn span_to_range(ctxt: &PermissionsCtxt, span: Span) -> Option<CharRange> {
+  let source_map = ctxt.tcx.sess.source_map();
+  CharRange::from_span(span, source_map).ok()
+}
+
+/// Collects, for each lifetime in a HIR type, the span of the innermost
+/// type it belongs to. E.g. the two lifetimes of `&mut Vec<&i32>` are
+/// spanned by the whole type and by `&i32`.
+#[derive(Default)]
+struct LifetimeTypeSpans {
+  enclosing: Vec<Span>,
+  spans: Vec<Span>,
+}
+
+impl<'hir> Visitor<'hir> for LifetimeTypeSpans {
+  fn visit_ty(&mut self, ty: &'hir hir::Ty<'hir, AmbigArg>) {
+    self.enclosing.push(ty.span);
+    intravisit::walk_ty(self, ty);
+    self.enclosing.pop();
+  }
+
+  fn visit_lifetime(&mut self, _lifetime: &'hir hir::Lifetime) {
+    if let Some(span) = self.enclosing.last() {
+      self.spans.push(*span);
+    }
+  }
+}
+
+/// The spans naming each lifetime in `hir_ty`, in the order in which
+/// their regions are found by walking the lowered type.
+fn lifetime_type_spans(hir_ty: &hir::Ty) -> Vec<Span> {
+  let mut visitor = LifetimeTypeSpans::default();
+  visitor.visit_ty_unambig(hir_ty);
+  visitor.spans
+}
+
+/// Pair each region in the renumbered `body_ty` with its name in the
+/// user-written `sig_ty`.
+///
+/// Elided regions are named after the type in `hir_ty` they belong to.
+/// If the regions of `hir_ty` can't be matched up with those of `sig_ty`,
+/// e.g. because of a type alias, they're named after all of `hir_ty`.
+fn name_regions_in<'tcx>(
+  ctxt: &PermissionsCtxt<'tcx>,
+  body_ty: Ty<'tcx>,
+  sig_ty: Ty<'tcx>,
+  hir_ty: &hir::Ty,
+) -> Vec<(Origin, RegionName)> {
+  let tcx = ctxt.tcx;
+  let body_regions = body_ty
+    .walk()
+    .filter_map(|arg| arg.as_region())
+    .collect::<Vec<_>>();
+  let sig_regions = sig_ty
+    .walk()
+    .filter_map(|arg| arg.as_region())
+    .collect::<Vec<_>>();
+
+  // The two types should only differ in their regions, if they don't
+  // (e.g. because of normalization) we can't reliably pair them up.
+  if body_regions.len() != sig_regions.len() {
+    return Vec::default();
+  }
+
+  let mut type_spans = lifetime_type_spans(hir_ty);
+  if type_spans.len() != sig_regions.len() {
+    type_spans = vec![hir_ty.span; sig_regions.len()];
+  }
+
+  let elided = |span: Span| {
+    let span = span
+      .as_local(ctxt.body_with_facts.body.span)
+      .unwrap_or(span);
+    let snippet = tcx.sess.source_map().span_to_snippet(span).ok()?;
+    let range = span_to_range(ctxt, span)?;
+    Some(RegionName::Elided { snippet, range })
+  };
+
+  body_regions
+    .into_iter()
+    .zip(sig_regions)
+    .zip(type_spans)
+    .filter_map(|((body_region, sig_region), span)| {
+      let ty::ReVar(vid) = body_region.kind() else {
+        return None;
+      };
+
+      let name = match sig_region.get_name(tcx) {
+        Some(name) if sig_region.is_named(tcx) => RegionName::Named {
+          name: name.to_string(),
+        },
+        _ => elided(span)?,
+      };
+
+      Some((vid.into(), name))
+    })
+    .collect()
+}
+
+fn signature_region_names(
+  ctxt: &PermissionsCtxt,
+) -> HashMap<Origin, RegionName> {
+  let tcx = ctxt.tcx;
+  let body = &ctxt.body_with_facts.body;
+
+  if !matches!(tcx.def_kind(ctxt.def_id), DefKind::Fn | DefKind::AssocFn) {
+    return HashMap::default();
+  }
+
+  let hir_id = tcx.local_def_id_to_hir_id(ctxt.def_id.expect_local());
+  let Some(decl) = tcx.hir_fn_decl_by_hir_id(hir_id) else {
+    return HashMap::default();
+  };
+
+  let sig = tcx
+    .fn_sig(ctxt.def_id)
+    .instantiate_identity()
+    .skip_norm_wip()
+    .skip_binder();
+
+  let inputs = body
+    .args_iter()
+    .map(|local| body.local_decls[local].ty)
+    .zip(sig.inputs().iter().copied())
+    .zip(decl.inputs.iter());
+  // A default return type is `()`, which has no regions to name.
+  let output = match decl.output {
+    FnRetTy::Return(hir_ty) => Some(((body.return_ty(), sig.output()), hir_ty)),
+    FnRetTy::DefaultReturn(_) => None,
+  };
+
+  inputs
+    .chain(output)
+    .flat_map(|((body_ty, sig_ty), hir_ty)| {
+      name_regions_in(ctxt, body_ty, sig_ty, hir_ty)
+    })
+    .unique_by(|(origin, _)| *origin)
+    .collect()
+}
+
+fn borrow_region_names(
+  ctxt: &PermissionsCtxt,
+) -> HashMap<Origin, (Span, RegionName)> {
+  ctxt
+    .borrow_set
+    .location_map()
+    .iter()
+    .filter_map(|(loc, bd)| {
+      let span = ctxt.location_to_span(*loc);
+      if span.is_dummy() || span.is_empty() {
+        return None;
+      }
+      let range = span_to_range(ctxt, span)?;
+      Some((bd.region().into(), (span, RegionName::Borrow { range })))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{analysis::compute_permissions, test_utils as tu};
+
+  #[test]
+  fn elided_regions_named_after_their_type() {
+    let code = r"
+fn add_ref<'a>(v: &mut Vec<&i32>, n: &'a String, t: (&u8, &u16)) {}
+";
+    tu::compile_normal(code, |tcx| {
+      tu::for_each_body(tcx, |body_id, wfacts| {
+        let ctxt = compute_permissions(tcx, body_id, wfacts);
+        let mut names = signature_region_names(&ctxt)
+          .into_values()
+          .map(|name| match name {
+            RegionName::Named { name } => name,
+            RegionName::Elided { snippet, .. } => snippet,
+            RegionName::Borrow { .. } => unreachable!("not a signature region"),
+          })
+          .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["&i32", "&mut Vec<&i32>", "&u16", "&u8", "'a"]);
+      });
+    });
+  }
+}
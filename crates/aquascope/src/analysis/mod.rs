@@ -6,6 +6,7 @@ pub mod ir_mapper;
 pub mod permissions;
 mod scrape_hir;
 pub mod stepper;
+pub mod summaries;
 
 use std::{
   cell::RefCell,
@@ -17,7 +18,8 @@ use std::{
 use boundaries::PermissionsBoundary;
 pub use boundaries::compute_permission_boundaries;
 pub use find_bindings::find_bindings;
-use ir_mapper::{GatherMode, IRMapper};
+use ir_mapper::{GatherMode, IRMapper, region_name::RegionNames};
+use itertools::Itertools;
 use permissions::{
   Loan, Move, PermissionsCtxt, Point, RefinementRegion, Refiner,
 };
@@ -29,11 +31,18 @@ use rustc_span::{self, Span};
 use rustc_utils::{
   BodyExt, SpanExt,
   mir::borrowck_facts,
-  source_map::range::{CharPos, CharRange},
+  source_map::{
+    find_bodies::find_bodies,
+    range::{CharPos, CharRange},
+  },
 };
 use serde::Serialize;
-use stepper::PermissionsLineDisplay;
 pub use stepper::compute_permission_steps;
+use stepper::{
+  PermissionsLineDisplay,
+  focus::{STEP_FOCUS, StepFocus},
+};
+use summaries::FunctionSummary;
 use ts_rs::TS;
 
 thread_local! {
@@ -108,6 +117,12 @@ pub struct LoanPoints(pub HashMap<LoanKey, CharRange>);
 #[ts(export)]
 pub struct MovePoints(pub HashMap<MoveKey, CharRange>);
 
+/// Activation points of two-phase loans, loans that
+/// are not two-phase are activated at their [`LoanPoints`].
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct LoanActivations(pub HashMap<LoanKey, CharRange>);
+
 #[derive(Clone, Debug, Serialize, TS)]
 #[ts(export)]
 pub struct LoanRegions(pub HashMap<LoanKey, RefinementRegion>);
@@ -272,6 +287,20 @@ pub fn compute_permissions<'tcx>(
   })
 }
 
+/// Find all bodies in the crate that should be analyzed.
+///
+/// The body of an `async fn` only moves its arguments into a coroutine,
+/// the code as written lives in the coroutine which is its own body.
+pub fn find_analysis_bodies(tcx: TyCtxt) -> Vec<(Span, BodyId)> {
+  find_bodies(tcx)
+    .into_iter()
+    .filter(|&(_, body_id)| {
+      let def_id = tcx.hir_body_owner_def_id(body_id);
+      !tcx.asyncness(def_id).is_async()
+    })
+    .collect()
+}
+
 // ------------------------------------------------
 
 #[derive(Clone, Debug, Serialize, TS)]
@@ -288,6 +317,7 @@ pub type AquascopeResult<T> = ::std::result::Result<T, AquascopeError>;
 pub struct AquascopeAnalysis<'tcx> {
   pub(crate) permissions: PermissionsCtxt<'tcx>,
   pub(crate) ir_mapper: IRMapper<'tcx>,
+  pub(crate) region_names: RegionNames,
 }
 
 impl From<anyhow::Error> for AquascopeError {
@@ -303,9 +333,11 @@ pub struct AnalysisOutput {
   pub boundaries: Vec<PermissionsBoundary>,
   pub steps: Vec<PermissionsLineDisplay>,
   pub loan_points: LoanPoints,
+  pub loan_activations: LoanActivations,
   pub loan_regions: LoanRegions,
   pub move_points: MovePoints,
   pub move_regions: MoveRegions,
+  pub summaries: Vec<FunctionSummary>,
 }
 
 impl<'tcx> AquascopeAnalysis<'tcx> {
@@ -316,9 +348,11 @@ impl<'tcx> AquascopeAnalysis<'tcx> {
     let body = &permissions.body_with_facts.body;
 
     let ir_mapper = IRMapper::new(tcx, body, GatherMode::IgnoreCleanup);
+    let region_names = RegionNames::new(&permissions);
     AquascopeAnalysis {
       permissions,
       ir_mapper,
+      region_names,
     }
   }
 
@@ -342,21 +376,37 @@ impl<'tcx> AquascopeAnalysis<'tcx> {
     );
 
     let boundaries = compute_permission_boundaries(&analysis_ctxt)?;
-    let steps = compute_permission_steps(&analysis_ctxt)?;
-
-    let (loan_points, loan_regions) = analysis_ctxt.construct_loan_info();
+    let steps = STEP_FOCUS.get(|focus| {
+      compute_permission_steps(
+        &analysis_ctxt,
+        focus.unwrap_or(&StepFocus::default()),
+      )
+    })?;
+
+    let (loan_points, loan_activations, loan_regions) =
+      analysis_ctxt.construct_loan_info();
     let (move_points, move_regions) = analysis_ctxt.construct_move_info();
 
     let body_range = analysis_ctxt.span_to_range(body.span);
 
+    // Summarize each local function called in this body, once.
+    let summaries = boundaries
+      .iter()
+      .filter_map(|b| b.summary.as_ref()?.def_id.as_local())
+      .unique()
+      .filter_map(|def_id| summaries::summarize(tcx, def_id))
+      .collect::<Vec<_>>();
+
     Ok(AnalysisOutput {
       body_range,
       boundaries,
       steps,
       loan_points,
+      loan_activations,
       loan_regions,
       move_points,
       move_regions,
+      summaries,
     })
   }
 
@@ -373,14 +423,15 @@ impl<'tcx> AquascopeAnalysis<'tcx> {
     CharRange::from_span(span, source_map).unwrap()
   }
 
-  fn construct_loan_info(&self) -> (LoanPoints, LoanRegions) {
+  fn construct_loan_info(&self) -> (LoanPoints, LoanActivations, LoanRegions) {
     let loan_regions = &self.permissions.loan_regions.as_ref().unwrap();
 
     let loans_to_spans = loan_regions
       .keys()
       .filter_map(|loan| {
-        // TODO: using `reserve_location` is not exactly accurate because this
-        // could be a two-phase borrow. This needs to use the `activation_location`.
+        // NOTE: the loan point is always the reservation, this is where the
+        // borrow appears in the source. Two-phase borrows are activated later,
+        // and those activations are tracked separately in `LoanActivations`.
         let loan_loc = self.permissions.borrow_set[*loan].reserve_location();
         let loan_span = self.permissions.location_to_span(loan_loc);
 
@@ -438,17 +489,38 @@ impl<'tcx> AquascopeAnalysis<'tcx> {
           .map(|s| self.span_to_range(s))
           .collect::<Vec<_>>();
 
+        let reserved_ranges = self
+          .loan_reserved_spans(**loan)
+          .into_iter()
+          .map(|s| self.span_to_range(s))
+          .collect::<Vec<_>>();
+
+        let region = self.permissions.loan_to_borrow(**loan).region();
+        let region_name =
+          self.region_names.name_of(&self.permissions, region.into());
+
         let loan_key: LoanKey = (*loan).into();
 
         let rr = RefinementRegion {
           refiner_point: Refiner::Loan(loan_key),
           refined_ranges: active_nodes,
+          reserved_ranges,
+          region_name,
         };
 
         (loan_key, rr)
       })
       .collect::<HashMap<_, _>>();
 
+    let loan_to_activations = loans_to_spans
+      .keys()
+      .filter_map(|loan| {
+        let loc = self.permissions.loan_activation_location(**loan)?;
+        let span = self.permissions.location_to_span(loc);
+        (!span.is_empty()).then(|| ((*loan).into(), self.span_to_range(span)))
+      })
+      .collect::<HashMap<_, _>>();
+
     let loan_to_ranges = loans_to_spans
       .into_iter()
       .map(|(loan, span)| {
@@ -458,7 +530,34 @@ impl<'tcx> AquascopeAnalysis<'tcx> {
       })
       .collect::<HashMap<_, _>>();
 
-    (LoanPoints(loan_to_ranges), LoanRegions(loan_to_regions))
+    (
+      LoanPoints(loan_to_ranges),
+      LoanActivations(loan_to_activations),
+      LoanRegions(loan_to_regions),
+    )
+  }
+
+  /// The source spans where a two-phase `loan` is reserved but not yet activated.
+  fn loan_reserved_spans(&self, loan: Loan) -> Vec<Span> {
+    let ctxt = &self.permissions;
+    let points = ctxt
+      .permissions_output
+      .loan_reserved_at
+      .iter()
+      .filter_map(|(point, loans)| loans.contains(&loan).then_some(*point))
+      .filter(|point| ctxt.is_point_operational(*point));
+
+    let spans = self
+      .points_to_spans(points)
+      .into_iter()
+      .map(|span| {
+        span
+          .as_local(ctxt.body_with_facts.body.span)
+          .unwrap_or(span)
+      })
+      .collect::<Vec<_>>();
+
+    smooth_elements(spans)
   }
 
   // FIXME(gavinleroy): the two `construct_XXX` methods could
@@ -510,6 +609,8 @@ impl<'tcx> AquascopeAnalysis<'tcx> {
         let region = RefinementRegion {
           refiner_point: Refiner::Move(move_key),
           refined_ranges,
+          reserved_ranges: Vec::default(),
+          region_name: None,
         };
         Some((move_key, region))
       })
@@ -606,3 +707,41 @@ impl<'tcx> AquascopeAnalysis<'tcx> {
     spans
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::test_utils as tu;
+
+  #[test]
+  fn two_phase_reservation_within_refinement() {
+    let code = r"
+fn push_len(mut vec: Vec<usize>) {
+  vec.push(vec.len());
+}
+";
+    tu::compile_normal(code, |tcx| {
+      tu::for_each_body(tcx, |body_id, _| {
+        let output = AquascopeAnalysis::run(tcx, body_id, false).unwrap();
+
+        let pos = |p: CharPos| (p.line, p.column);
+        let contains = |outer: &CharRange, inner: &CharRange| {
+          pos(outer.start) <= pos(inner.start)
+            && pos(inner.end) <= pos(outer.end)
+        };
+
+        let regions = output.loan_regions.values().collect::<Vec<_>>();
+        assert!(regions.iter().any(|r| !r.reserved_ranges.is_empty()));
+        for region in regions {
+          for reserved in &region.reserved_ranges {
+            assert!(
+              region.refined_ranges.iter().any(|r| contains(r, reserved)),
+              "reservation {reserved:?} outside of {:?}",
+              region.refined_ranges
+            );
+          }
+        }
+      });
+    });
+  }
+}
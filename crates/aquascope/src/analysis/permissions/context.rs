@@ -13,7 +13,7 @@
 use polonius_engine::{AllFacts, FactTypes, Output as PEOutput};
 use rustc_borrowck::consumers::{
   BodyWithBorrowckFacts, BorrowData, BorrowSet, PoloniusLocationTable,
-  RustcFacts,
+  RustcFacts, TwoPhaseActivation,
 };
 use rustc_data_structures::fx::{FxHashMap as HashMap, FxHashSet as HashSet};
 use rustc_hir::{BodyId, Mutability, def_id::DefId};
@@ -30,14 +30,14 @@ use smallvec::{SmallVec, smallvec};
 use crate::analysis::{
   LoanRefined,
   permissions::{
-    AquascopeFacts, Loan, LoanKey, Move, MoveKey, Origin, Output, Path,
-    Permissions, PermissionsData, PermissionsDomain, Point, Variable,
-    flow::RegionFlows,
+    AquascopeFacts, InteriorMutability, Loan, LoanKey, Move, MoveKey, Origin,
+    Output, Path, Permissions, PermissionsData, PermissionsDomain, Point,
+    Variable, flow::RegionFlows,
   },
 };
 
 /// A path as defined in rustc.
-type MoveablePath = <RustcFacts as FactTypes>::Path;
+pub(crate) type MoveablePath = <RustcFacts as FactTypes>::Path;
 
 pub struct PermissionsCtxt<'tcx> {
   pub tcx: TyCtxt<'tcx>,
@@ -105,7 +105,7 @@ impl<'tcx> PermissionsCtxt<'tcx> {
     self.place_data[p]
   }
 
-  fn location_table(&self) -> &PoloniusLocationTable {
+  pub(crate) fn location_table(&self) -> &PoloniusLocationTable {
     self.body_with_facts.location_table.as_ref().unwrap()
   }
 
@@ -161,6 +161,19 @@ impl<'tcx> PermissionsCtxt<'tcx> {
     &self.borrow_set[l]
   }
 
+  /// The location where a two-phase loan is activated, if any.
+  ///
+  /// Loans that are not two-phase are activated at their reservation
+  /// and return `None`, as do two-phase loans that are never activated.
+  pub fn loan_activation_location(&self, l: Loan) -> Option<Location> {
+    match self.borrow_set[l].activation_location() {
+      TwoPhaseActivation::ActivatedAt(loc) => Some(loc),
+      TwoPhaseActivation::NotTwoPhase | TwoPhaseActivation::NotActivated => {
+        None
+      }
+    }
+  }
+
   // Predicates
 
   pub fn is_universal_subset(&self, (from, to): (Origin, Origin)) -> bool {
@@ -204,6 +217,27 @@ impl<'tcx> PermissionsCtxt<'tcx> {
     self.is_mutable_borrow(&self.borrow_set[loan])
   }
 
+  /// Is the two-phase `loan` reserved, but not yet activated, at `point`?
+  pub fn is_loan_reserved_at(&self, loan: Loan, point: Point) -> bool {
+    self
+      .permissions_output
+      .loan_reserved_at
+      .get(&point)
+      .is_some_and(|loans| loans.contains(&loan))
+  }
+
+  /// Is the place reached by dereferencing a raw pointer?
+  ///
+  /// The compiler doesn't check the permissions of these places,
+  /// the programmer is responsible for upholding them.
+  pub fn is_through_raw_pointer(&self, place: &Place<'tcx>) -> bool {
+    let body = &self.body_with_facts.body;
+    place.iter_projections().any(|(prefix, elem)| {
+      matches!(elem, ProjectionElem::Deref)
+        && prefix.ty(&body.local_decls, self.tcx).ty.is_raw_ptr()
+    })
+  }
+
   pub fn is_declared_readonly(&self, place: &Place<'tcx>) -> bool {
     self.body_with_facts.body.local_decls[place.local].mutability
       != Mutability::Mut
@@ -231,6 +265,23 @@ impl<'tcx> PermissionsCtxt<'tcx> {
     !self.permissions_output.never_write.contains(&path)
   }
 
+  /// Is this path reached through a raw pointer?
+  pub fn is_path_unchecked(&self, path: Path) -> bool {
+    self.permissions_output.unchecked.contains(&path)
+  }
+
+  /// The type checking writes to this path at runtime, if any.
+  pub fn path_interior_mutability(
+    &self,
+    path: Path,
+  ) -> Option<InteriorMutability> {
+    self
+      .permissions_output
+      .interior_mutability
+      .get(&path)
+      .copied()
+  }
+
   /// Does a Path's type allow it to be dropped?
   /// NOTE: the utility is the negation of the datalog rule.
   ///
@@ -298,8 +349,11 @@ impl<'tcx> PermissionsCtxt<'tcx> {
       type_copyable: ty.is_copyable(self.tcx, self.typing_env),
       path_moved: None,
       path_uninitialized: false,
+      path_definitely_uninitialized: false,
       loan_refined: LoanRefined::None,
       loan_drop_refined: None,
+      interior_mutability: None,
+      unchecked: false,
     }
   }
 
@@ -359,6 +413,10 @@ impl<'tcx> PermissionsCtxt<'tcx> {
       .path_maybe_uninitialized_on_entry
       .get(point)
       .unwrap_or(empty_set);
+    let path_maybe_initialized = pms
+      .path_maybe_initialized_on_entry
+      .get(point)
+      .unwrap_or(empty_set);
     let path_moved = pms.move_refined.get(point).unwrap_or(empty_hash_move);
     let loan_read_refined =
       pms.loan_read_refined.get(point).unwrap_or(empty_hash_loan);
@@ -371,6 +429,8 @@ impl<'tcx> PermissionsCtxt<'tcx> {
     let type_droppable = self.is_path_drop_enabled(*path);
     let type_copyable = self.is_path_copyable(*path);
     let path_uninitialized = path_uninitialized.contains(path);
+    let path_definitely_uninitialized =
+      path_uninitialized && !path_maybe_initialized.contains(path);
 
     let path_moved: Option<MoveKey> =
       path_moved.get(path).map(Into::<MoveKey>::into);
@@ -395,15 +455,21 @@ impl<'tcx> PermissionsCtxt<'tcx> {
     let loan_drop_refined: Option<LoanKey> =
       loan_drop_refined.get(path).map(Into::<LoanKey>::into);
 
+    let interior_mutability = self.path_interior_mutability(*path);
+    let unchecked = self.is_path_unchecked(*path);
+
     PermissionsData {
       type_droppable,
       type_writeable,
       type_copyable,
       is_live,
       path_uninitialized,
+      path_definitely_uninitialized,
       path_moved,
       loan_refined,
       loan_drop_refined,
+      interior_mutability,
+      unchecked,
     }
   }
 
@@ -426,8 +492,11 @@ impl<'tcx> PermissionsCtxt<'tcx> {
           type_copyable: false,
           path_moved: None,
           path_uninitialized: false,
+          path_definitely_uninitialized: false,
           loan_refined: LoanRefined::None,
           loan_drop_refined: None,
+          interior_mutability: None,
+          unchecked: false,
         })
       })
       .collect::<HashMap<_, _>>()
@@ -0,0 +1,392 @@
+//! Export of the Polonius and Aquascope facts for a body.
+//!
+//! Every input and derived relation is written as a tab-separated `.facts`
+//! file, in the same format used by Polonius and Soufflé. Each cell is a
+//! quoted string, for example the relation `loan_live_at(Loan, Point)`
+//! would contain rows such as:
+//!
+//! ```text
+//! "bw0"    "Mid(bb0[2])"
+//! ```
+//!
+//! Atoms are only meaningful relative to the body they come from, therefore
+//! a `mapping.tsv` file is written alongside the facts which maps the
+//! paths, points, loans, and moves back to MIR places and source locations.
+
+use std::{
+  any::Any,
+  fmt,
+  fs::{self, File},
+  io::{BufWriter, Write},
+  path::{Path as FsPath, PathBuf},
+};
+
+use anyhow::Result;
+use rustc_hir::BodyId;
+use rustc_index::Idx;
+use rustc_middle::ty::TyCtxt;
+use rustc_utils::mir::borrowck_facts;
+
+use super::{Loan, PermissionsCtxt, Point};
+use crate::analysis::compute_permissions;
+
+/// A single atom of a relation.
+///
+/// Atoms are written with their `Debug` representation, except for points
+/// which are written as the MIR location they stand for.
+trait FactCell {
+  fn to_fact_string(&self, ctxt: &PermissionsCtxt) -> String;
+}
+
+impl<T: fmt::Debug + 'static> FactCell for T {
+  fn to_fact_string(&self, ctxt: &PermissionsCtxt) -> String {
+    match (self as &dyn Any).downcast_ref::<Point>() {
+      Some(&point) => {
+        format!("{:?}", ctxt.location_table().to_rich_location(point))
+      }
+      None => format!("{self:?}"),
+    }
+  }
+}
+
+/// A row of a relation, i.e. a tuple of [`FactCell`]s.
+trait FactRow {
+  fn write(
+    &self,
+    out: &mut dyn Write,
+    ctxt: &PermissionsCtxt,
+  ) -> std::io::Result<()>;
+}
+
+macro_rules! impl_fact_row {
+  ($($t:ident),+) => {
+    impl<$($t: FactCell),+> FactRow for ($($t,)+) {
+      #[allow(non_snake_case)]
+      fn write(
+        &self,
+        out: &mut dyn Write,
+        ctxt: &PermissionsCtxt,
+      ) -> std::io::Result<()> {
+        let ($($t,)+) = self;
+        let cells = [$($t.to_fact_string(ctxt)),+];
+        let quoted =
+          cells.iter().map(|c| format!("{c:?}")).collect::<Vec<_>>();
+        writeln!(out, "{}", quoted.join("\t"))
+      }
+    }
+  };
+}
+
+impl_fact_row!(A);
+impl_fact_row!(A, B);
+impl_fact_row!(A, B, C);
+impl_fact_row!(A, B, C, D);
+
+struct FactWriter<'a, 'tcx> {
+  ctxt: &'a PermissionsCtxt<'tcx>,
+  dir: &'a FsPath,
+}
+
+impl FactWriter<'_, '_> {
+  fn write_facts_to_path<R: FactRow>(
+    &self,
+    rows: impl IntoIterator<Item = R>,
+    file_name: &str,
+  ) -> Result<()> {
+    let path = self.dir.join(format!("{file_name}.facts"));
+    let mut file = BufWriter::new(File::create(path)?);
+    for row in rows {
+      row.write(&mut file, self.ctxt)?;
+    }
+    file.flush()?;
+    Ok(())
+  }
+}
+
+/// Write all facts for `ctxt` into the directory `dir`.
+pub fn write_facts_to_dir(ctxt: &PermissionsCtxt, dir: &FsPath) -> Result<()> {
+  fs::create_dir_all(dir)?;
+  let writer = FactWriter { ctxt, dir };
+
+  macro_rules! write_input_facts {
+    ($($field:ident),* $(,)?) => {
+      $(
+        writer.write_facts_to_path(
+          ctxt.polonius_input_facts.$field.iter().copied(),
+          stringify!($field),
+        )?;
+      )*
+    };
+  }
+
+  write_input_facts![
+    loan_issued_at,
+    cfg_edge,
+    loan_killed_at,
+    subset_base,
+    loan_invalidated_at,
+    var_used_at,
+    var_defined_at,
+    var_dropped_at,
+    use_of_var_derefs_origin,
+    drop_of_var_derefs_origin,
+    child_path,
+    path_is_var,
+    path_assigned_at_base,
+    path_moved_at_base,
+    path_accessed_at_base,
+    known_placeholder_subset,
+    placeholder,
+  ];
+
+  writer.write_facts_to_path(
+    ctxt
+      .polonius_input_facts
+      .universal_region
+      .iter()
+      .map(|&o| (o,)),
+    "universal_region",
+  )?;
+
+  // Derived Polonius relations, these are keyed by the `Point`
+  // and flattened into `(..., Point)` rows.
+  let polonius = &ctxt.polonius_output;
+
+  macro_rules! write_point_keyed {
+    ($relation:expr, $name:literal) => {
+      writer.write_facts_to_path(
+        $relation
+          .iter()
+          .flat_map(|(&point, values)| values.iter().map(move |&v| (v, point))),
+        $name,
+      )?;
+    };
+  }
+
+  write_point_keyed!(polonius.errors, "errors");
+  write_point_keyed!(polonius.loan_live_at, "loan_live_at");
+  write_point_keyed!(polonius.origin_live_on_entry, "origin_live_on_entry");
+  write_point_keyed!(polonius.loan_invalidated_at, "loan_invalidated_at");
+  write_point_keyed!(polonius.var_live_on_entry, "var_live_on_entry");
+  write_point_keyed!(polonius.move_errors, "move_errors");
+
+  writer.write_facts_to_path(
+    polonius.subset_errors.iter().flat_map(|(&point, errs)| {
+      errs.iter().map(move |&(o1, o2)| (o1, o2, point))
+    }),
+    "subset_errors",
+  )?;
+
+  writer.write_facts_to_path(
+    polonius
+      .origin_contains_loan_at
+      .iter()
+      .flat_map(|(&point, m)| {
+        m.iter().flat_map(move |(&origin, loans)| {
+          loans.iter().map(move |&loan| (origin, loan, point))
+        })
+      }),
+    "origin_contains_loan_at",
+  )?;
+
+  writer.write_facts_to_path(
+    polonius.subset.iter().flat_map(|(&point, m)| {
+      m.iter()
+        .flat_map(move |(&o1, o2s)| o2s.iter().map(move |&o2| (o1, o2, point)))
+    }),
+    "subset",
+  )?;
+
+  // Derived Aquascope relations.
+  let output = &ctxt.permissions_output;
+
+  writer.write_facts_to_path(
+    output.never_write.iter().map(|&path| (path,)),
+    "never_write",
+  )?;
+
+  macro_rules! write_refined {
+    ($relation:ident) => {
+      writer.write_facts_to_path(
+        output.$relation.iter().flat_map(|(&point, m)| {
+          m.iter()
+            .map(move |(&path, &refiner)| (path, refiner, point))
+        }),
+        stringify!($relation),
+      )?;
+    };
+  }
+
+  write_refined!(loan_read_refined);
+  write_refined!(loan_write_refined);
+  write_refined!(loan_drop_refined);
+  write_refined!(move_refined);
+
+  write_point_keyed!(
+    output.path_maybe_uninitialized_on_entry,
+    "path_maybe_uninitialized_on_entry"
+  );
+  write_point_keyed!(
+    output.path_maybe_initialized_on_entry,
+    "path_maybe_initialized_on_entry"
+  );
+  write_point_keyed!(output.move_live_at, "move_live_at");
+  write_point_keyed!(output.loan_reserved_at, "loan_reserved_at");
+
+  write_mapping(ctxt, &dir.join("mapping.tsv"))?;
+
+  Ok(())
+}
+
+/// Write a mapping from atoms to their MIR and source-level meaning.
+///
+/// Each line has the form `kind atom mir source`, separated by tabs.
+fn write_mapping(ctxt: &PermissionsCtxt, file: &FsPath) -> Result<()> {
+  let source_map = ctxt.tcx.sess.source_map();
+  let body = &ctxt.body_with_facts.body;
+  let mut out = BufWriter::new(File::create(file)?);
+
+  for (path, place) in ctxt.place_data.iter_enumerated() {
+    let span = body.local_decls[place.local].source_info.span;
+    writeln!(
+      out,
+      "path\t{}\t{place:?}\t{}",
+      path.to_fact_string(ctxt),
+      source_map.span_to_diagnostic_string(span)
+    )?;
+  }
+
+  for (mpath, move_path) in ctxt.move_data.move_paths.iter_enumerated() {
+    writeln!(
+      out,
+      "move_path\t{}\t{:?}\t",
+      mpath.to_fact_string(ctxt),
+      move_path.place
+    )?;
+  }
+
+  for point in ctxt.location_table().all_points() {
+    let loc = ctxt.point_to_location(point);
+    let span = body.source_info(loc).span;
+    writeln!(
+      out,
+      "point\t{}\t{loc:?}\t{}",
+      point.to_fact_string(ctxt),
+      source_map.span_to_diagnostic_string(span)
+    )?;
+  }
+
+  // Loans index into the borrow set in order of their reservation.
+  for (i, borrow) in ctxt.borrow_set.location_map().values().enumerate() {
+    let loan = Loan::new(i);
+    let span = ctxt.location_to_span(borrow.reserve_location());
+    writeln!(
+      out,
+      "loan\t{}\t{borrow}\t{}",
+      loan.to_fact_string(ctxt),
+      source_map.span_to_diagnostic_string(span)
+    )?;
+  }
+
+  for (mv, move_out) in ctxt.move_data.moves.iter_enumerated() {
+    let span = body.source_info(move_out.source).span;
+    writeln!(
+      out,
+      "move\t{}\t{:?}\t{}",
+      mv.to_fact_string(ctxt),
+      ctxt.move_data.move_paths[move_out.path].place,
+      source_map.span_to_diagnostic_string(span)
+    )?;
+  }
+
+  out.flush()?;
+  Ok(())
+}
+
+/// Compute the permissions for `body_id` and write its facts
+/// into a subdirectory of `out_dir` named after the body.
+///
+/// The name is suffixed with the index of the body's owner, as distinct
+/// paths can have the same name once sanitized, e.g. `a::b` and `a__b`.
+///
+/// Returns the directory the facts were written to.
+pub fn dump_body_facts(
+  tcx: TyCtxt,
+  body_id: BodyId,
+  out_dir: &FsPath,
+) -> Result<PathBuf> {
+  let def_id = tcx.hir_body_owner_def_id(body_id);
+  let bwf = borrowck_facts::get_body_with_borrowck_facts(tcx, def_id);
+  let ctxt = compute_permissions(tcx, body_id, bwf);
+
+  let body_name = tcx
+    .def_path_str(def_id)
+    .chars()
+    .map(|c| if c.is_alphanumeric() { c } else { '_' })
+    .collect::<String>();
+
+  let dir =
+    out_dir.join(format!("{body_name}_{}", def_id.local_def_index.as_usize()));
+  write_facts_to_dir(&ctxt, &dir)?;
+
+  log::debug!("Wrote facts for {def_id:?} to {}", dir.display());
+
+  Ok(dir)
+}
+
+#[cfg(test)]
+mod test {
+  use std::env;
+
+  use super::*;
+  use crate::{analysis::find_analysis_bodies, test_utils as tu};
+
+  #[test]
+  fn dump_facts_per_body() {
+    let code = r"
+mod a {
+  pub fn b() {
+    let mut v = vec![1];
+    let r = &v;
+    let _n = r.len();
+    v.push(2);
+  }
+}
+
+fn a__b() {}
+";
+    let out_dir =
+      env::temp_dir().join(format!("aquascope-facts-{}", std::process::id()));
+    let mut dirs = Vec::default();
+    tu::compile_normal(code, |tcx| {
+      for (_, body_id) in find_analysis_bodies(tcx) {
+        dirs.push(dump_body_facts(tcx, body_id, &out_dir).unwrap());
+      }
+    });
+
+    // Both bodies are named `a__b`, but get a directory of their own.
+    assert_eq!(dirs.len(), 2);
+    assert_ne!(dirs[0], dirs[1]);
+
+    for dir in &dirs {
+      let name = dir.file_name().unwrap().to_string_lossy();
+      assert!(name.starts_with("a__b_"), "unexpected directory {name}");
+
+      for relation in ["cfg_edge", "loan_issued_at", "loan_live_at"] {
+        let file = dir.join(format!("{relation}.facts"));
+        assert!(file.exists(), "missing {}", file.display());
+      }
+
+      let mapping = fs::read_to_string(dir.join("mapping.tsv")).unwrap();
+      assert!(mapping.lines().any(|line| line.starts_with("point\t")));
+    }
+
+    let has_loans = dirs.iter().any(|dir| {
+      let loans = fs::read_to_string(dir.join("loan_issued_at.facts"));
+      loans.is_ok_and(|loans| !loans.is_empty())
+    });
+    assert!(has_loans);
+
+    fs::remove_dir_all(&out_dir).unwrap();
+  }
+}
@@ -90,7 +90,10 @@ use rustc_utils::BodyExt;
 use serde::Serialize;
 use ts_rs::TS;
 
-use super::{Origin, PermissionsCtxt};
+use super::{
+  FLOW_ENGINE, FLOW_ENGINE_DEFAULT, FlowEngine, Origin, PermissionsCtxt,
+  flow_datalog::DatalogFlows,
+};
 
 #[derive(Ord, PartialOrd, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct SccIdx {
@@ -133,7 +136,7 @@ impl polonius_engine::Atom for SccIdx {
   }
 }
 
-#[derive(Debug, Clone, Serialize, TS)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, TS)]
 #[ts(export)]
 pub enum FlowEdgeKind {
   /// A local value is flowing into an abstract region.
@@ -185,6 +188,27 @@ pub struct RegionFlows {
 
   /// The set of local components that a given component could contain.
   contains_local: TransitiveRelation<SccIdx>,
+
+  /// The engine used to classify flows.
+  engine: FlowEngine,
+
+  /// The datalog formulation of the same analysis, only
+  /// computed when requested by the `engine`.
+  datalog: Option<DatalogFlows>,
+
+  /// Constraints classified differently by the two engines,
+  /// only computed for [`FlowEngine::CrossCheck`].
+  disagreements: Vec<FlowDisagreement>,
+}
+
+/// A constraint for which the imperative and datalog
+/// engines computed a different [`FlowEdgeKind`].
+#[derive(Debug, Clone)]
+pub struct FlowDisagreement {
+  pub from: Origin,
+  pub to: Origin,
+  pub imperative: FlowEdgeKind,
+  pub datalog: FlowEdgeKind,
 }
 
 impl RegionFlows {
@@ -205,6 +229,46 @@ impl RegionFlows {
       .is_empty()
   }
 
+  /// The abstract components that `origin` could contain.
+  pub fn abstract_sources(&self, origin: Origin) -> Vec<SccIdx> {
+    if let Some(datalog) = self.datalog_engine() {
+      return datalog
+        .abstract_sources(origin)
+        .into_iter()
+        .map(|a| self.scc(a))
+        .unique()
+        .collect();
+    }
+
+    self.contains_abstract.reachable_from(self.scc(origin))
+  }
+
+  /// The local components that `origin` could contain.
+  pub fn local_sources(&self, origin: Origin) -> Vec<SccIdx> {
+    if let Some(datalog) = self.datalog_engine() {
+      return datalog
+        .local_sources(origin)
+        .into_iter()
+        .map(|l| self.scc(l))
+        .unique()
+        .collect();
+    }
+
+    self.contains_local.reachable_from(self.scc(origin))
+  }
+
+  /// Can data in the abstract region `from` flow into `to`?
+  pub fn may_flow_into(&self, from: Origin, to: Origin) -> bool {
+    if let Some(datalog) = self.datalog_engine() {
+      return datalog.may_flow_into(from, to);
+    }
+
+    self
+      .contains_abstract
+      .reachable_from(self.scc(to))
+      .contains(&self.scc(from))
+  }
+
   pub fn has_local_member(&self, origin: Origin) -> bool {
     !self
       .contains_local
@@ -212,9 +276,61 @@ impl RegionFlows {
       .is_empty()
   }
 
+  /// Abstract components that flow from `from` into `to` without
+  /// a user-specified outlives relation.
+  ///
+  /// Each returned pair `(f, t)` is a missing `'f: 't` constraint.
+  pub(crate) fn missing_universal_constraints(
+    &self,
+    from: Origin,
+    to: Origin,
+  ) -> Vec<(SccIdx, SccIdx)> {
+    if let Some(datalog) = self.datalog_engine() {
+      return datalog
+        .missing_universal_constraints(from, to)
+        .into_iter()
+        .map(|(f, t)| (self.scc(f), self.scc(t)))
+        .unique()
+        .collect();
+    }
+
+    let abstract_to = self.contains_abstract.reachable_from(self.scc(to));
+    self
+      .contains_abstract
+      .reachable_from(self.scc(from))
+      .into_iter()
+      .flat_map(|from| abstract_to.iter().map(move |&to| (from, to)))
+      // was `'from: 'to` user-specified?
+      .filter(|&(from, to)| !self.specified_flows.contains(to, from))
+      .collect()
+  }
+
+  /// Constraints where the two flow engines disagree.
+  ///
+  /// This is always empty unless the flows were computed
+  /// with [`FlowEngine::CrossCheck`].
+  pub fn disagreements(&self) -> &[FlowDisagreement] {
+    &self.disagreements
+  }
+
+  /// The datalog flows, if they were selected to answer queries.
+  fn datalog_engine(&self) -> Option<&DatalogFlows> {
+    match (self.engine, &self.datalog) {
+      (FlowEngine::Datalog, Some(datalog)) => Some(datalog),
+      _ => None,
+    }
+  }
+
   /// Get the specific kind of flow edge that connects `from` and `to`.
-  #[allow(clippy::match_same_arms)]
   pub(crate) fn flow_kind(&self, from: Origin, to: Origin) -> FlowEdgeKind {
+    match self.datalog_engine() {
+      Some(datalog) => datalog.flow_kind(from, to),
+      None => self.imperative_flow_kind(from, to),
+    }
+  }
+
+  #[allow(clippy::match_same_arms)]
+  fn imperative_flow_kind(&self, from: Origin, to: Origin) -> FlowEdgeKind {
     let scc_from = self.constraint_graph.scc(from);
     let scc_to = self.constraint_graph.scc(to);
 
@@ -261,19 +377,7 @@ impl RegionFlows {
     // If both regions contain abstract, we check that all regions in `from`
     // are known to outlive those in `to`. Otherwise, there is a
     // missing constraint that needs to be specified.
-    if !self
-      .contains_abstract
-      .reachable_from(scc_from)
-      .into_iter()
-      .all(|from| {
-        self
-          .contains_abstract
-          .reachable_from(scc_to)
-          .into_iter()
-          // was `'from: 'to` user-specified?
-          .all(|to| self.specified_flows.contains(to, from))
-      })
-    {
+    if !self.missing_universal_constraints(from, to).is_empty() {
       return FlowEdgeKind::MissingUniversalConstraint;
     }
 
@@ -324,6 +428,55 @@ where
   tcb.freeze()
 }
 
+/// Abstract regions of the body, i.e. the placeholders and those
+/// only appearing in the return type, that appear in `vertices`.
+pub(super) fn abstract_origins(
+  ctxt: &PermissionsCtxt,
+  vertices: &HashSet<Origin>,
+) -> Vec<Origin> {
+  let body = &ctxt.body_with_facts.body;
+  ctxt
+    .polonius_input_facts
+    .placeholder
+    .iter()
+    .filter_map(|&(p, _)| vertices.contains(&p).then_some(p))
+    .chain(
+      body
+        .regions_in_return()
+        .map(|rg| PoloniusRegionVid::from(rg.as_var())),
+    )
+    .collect()
+}
+
+/// Regions of borrows that refer directly to body-owned memory.
+///
+/// If `Place::is_indirect` returns false, the caller knows
+/// that the Place refers to the same region of memory as its base.
+pub(super) fn local_source_origins(
+  ctxt: &PermissionsCtxt,
+) -> impl Iterator<Item = Origin> {
+  ctxt
+    .borrow_set
+    .location_map()
+    .values()
+    .filter(|bd| !bd.borrowed_place().is_indirect())
+    .map(|bd| PoloniusRegionVid::from(bd.region()))
+}
+
+/// Regions of erroneous borrows that are invalidated at an exit point.
+pub(super) fn dangling_origins<'a>(
+  ctxt: &'a PermissionsCtxt,
+) -> impl Iterator<Item = Origin> + 'a {
+  ctxt
+    .polonius_output
+    .errors
+    .values()
+    .flatten()
+    .map(|&loan| ctxt.loan_to_borrow(loan))
+    .filter(|bd| check_for_invalidation_at_exit(ctxt, bd))
+    .map(|bd| PoloniusRegionVid::from(bd.region()))
+}
+
 /// Check if the given borrow is invalidated by an exit point.
 ///
 /// Exit point would refer to a `StorageDead` or `Drop`.
@@ -379,7 +532,6 @@ fn check_for_invalidation_at_exit<'tcx>(
 pub fn compute_flows(ctxt: &mut PermissionsCtxt) {
   let timer = Instant::now();
   let tcx = ctxt.tcx;
-  let body = &ctxt.body_with_facts.body;
 
   // Compute the constraint graph with all regions.
   let constraints = ctxt
@@ -391,17 +543,7 @@ pub fn compute_flows(ctxt: &mut PermissionsCtxt) {
 
   let vertices = flatten_tuples(&constraints).collect::<HashSet<_>>();
 
-  let placeholders = ctxt
-    .polonius_input_facts
-    .placeholder
-    .iter()
-    .filter_map(|&(p, _)| vertices.contains(&p).then_some(p))
-    .chain(
-      body
-        .regions_in_return()
-        .map(|rg| PoloniusRegionVid::from(rg.as_var())),
-    )
-    .collect::<Vec<_>>();
+  let placeholders = abstract_origins(ctxt, &vertices);
 
   // Graph of constraints that need to be satisfied. This shows
   // us how data flows from one region into another.
@@ -411,7 +553,8 @@ pub fn compute_flows(ctxt: &mut PermissionsCtxt) {
     .chain(&placeholders)
     .max()
     .map_or(0, |o| o.index());
-  let constraint_graph = VecGraph::<_, false>::new(max_region + 1, constraints);
+  let constraint_graph =
+    VecGraph::<_, false>::new(max_region + 1, constraints.clone());
 
   let scc_constraints = Sccs::<Origin, SccIdx>::new(&constraint_graph);
   let num_sccs = scc_constraints.num_sccs();
@@ -458,15 +601,10 @@ pub fn compute_flows(ctxt: &mut PermissionsCtxt) {
   let specified_flows =
     flow_from_sources(placeholders.iter().copied(), &specified_flows_graph);
 
-  // Compute local sources:
-  // If `Place::is_indirect` returns false, the caller knows
-  // that the Place refers to the same region of memory as its base.
+  // Compute local sources.
   let mut local_sources = ChunkedBitSet::new_empty(num_sccs);
-  for (_, bd) in ctxt.borrow_set.location_map().iter() {
-    if !bd.borrowed_place().is_indirect() {
-      let scc = scc_constraints.scc(PoloniusRegionVid::from(bd.region()));
-      local_sources.insert(scc);
-    }
+  for origin in local_source_origins(ctxt) {
+    local_sources.insert(scc_constraints.scc(origin));
   }
 
   log::debug!(
@@ -484,15 +622,8 @@ pub fn compute_flows(ctxt: &mut PermissionsCtxt) {
     flow_from_sources(local_sources.iter(), &scc_constraints);
 
   let mut dangling_local_sources = ChunkedBitSet::new_empty(num_sccs);
-
-  for (_, loans) in ctxt.polonius_output.errors.iter() {
-    for &loan in loans.iter() {
-      let bd = ctxt.loan_to_borrow(loan);
-      if check_for_invalidation_at_exit(ctxt, bd) {
-        let scc = scc_constraints.scc(PoloniusRegionVid::from(bd.region()));
-        dangling_local_sources.insert(scc);
-      }
-    }
+  for origin in dangling_origins(ctxt) {
+    dangling_local_sources.insert(scc_constraints.scc(origin));
   }
 
   let mut abstract_sources = ChunkedBitSet::new_empty(num_sccs);
@@ -504,15 +635,53 @@ pub fn compute_flows(ctxt: &mut PermissionsCtxt) {
 
   log::debug!("Contains local:\n{contains_local:#?}");
 
-  let region_flows = RegionFlows {
+  let engine = FLOW_ENGINE.copied().unwrap_or(FLOW_ENGINE_DEFAULT);
+  let datalog = (engine != FlowEngine::Imperative)
+    .then(|| DatalogFlows::compute(ctxt, &constraints, &vertices));
+
+  let mut region_flows = RegionFlows {
     constraint_graph: scc_constraints,
     specified_flows,
     dangling_local_sources,
     abstract_sources,
     contains_abstract,
     contains_local,
+    engine,
+    datalog,
+    disagreements: Vec::default(),
   };
 
+  if let (FlowEngine::CrossCheck, Some(datalog)) =
+    (engine, &region_flows.datalog)
+  {
+    let disagreements = constraints
+      .iter()
+      .unique()
+      .filter_map(|&(from, to)| {
+        let imperative = region_flows.imperative_flow_kind(from, to);
+        let datalog = datalog.flow_kind(from, to);
+        (imperative != datalog).then_some(FlowDisagreement {
+          from,
+          to,
+          imperative,
+          datalog,
+        })
+      })
+      .collect::<Vec<_>>();
+
+    for d in &disagreements {
+      log::warn!(
+        "flow engines disagree on {:?} -> {:?}: imperative {:?}, datalog {:?}",
+        d.from,
+        d.to,
+        d.imperative,
+        d.datalog
+      );
+    }
+
+    region_flows.disagreements = disagreements;
+  }
+
   ctxt.region_flows = Some(region_flows);
 
   log::info!(
@@ -0,0 +1,285 @@
+//! A datalog formulation of the region flow analysis.
+//!
+//! The analysis in [`flow`](super::flow) works on the strongly connected
+//! components of the constraint graph. Here the same classification is
+//! stated as a handful of relations over origins, which makes it easier
+//! to check the rules by hand and serves as a reference implementation:
+//!
+//! ```text
+//! .decl reaches(Origin1, Origin2)
+//!
+//! reaches(Origin, Origin) :-
+//!   node(Origin).
+//!
+//! reaches(Origin1, Origin3) :-
+//!   reaches(Origin1, Origin2),
+//!   subset_base(Origin2, Origin3, _).
+//!
+//! .decl equivalent(Origin1, Origin2)
+//!
+//! equivalent(Origin1, Origin2) :-
+//!   reaches(Origin1, Origin2),
+//!   reaches(Origin2, Origin1).
+//!
+//! .decl specified(Origin1, Origin2)
+//!
+//! specified(Origin, Origin) :-
+//!   abstract_origin(Origin).
+//!
+//! specified(Origin1, Origin3) :-
+//!   specified(Origin1, Origin2),
+//!   known_placeholder_subset(Origin2, Origin3).
+//!
+//! specified(Origin1, Origin3) :-
+//!   specified(Origin1, Origin2),
+//!   equivalent(Origin2, Origin3),
+//!   abstract_origin(Origin3).
+//! ```
+//!
+//! Two origins are in the same component iff they are `equivalent`,
+//! and an origin contains a source iff the source `reaches` it.
+//! Selecting [`FlowEngine::CrossCheck`](super::FlowEngine) runs both
+//! implementations and reports where they disagree.
+
+use std::borrow::Cow;
+
+use datafrog::{Iteration, Relation};
+use itertools::Itertools;
+use rustc_data_structures::fx::{FxHashMap as HashMap, FxHashSet as HashSet};
+
+use super::{
+  Origin, PermissionsCtxt,
+  flow::{
+    FlowEdgeKind, abstract_origins, dangling_origins, local_source_origins,
+  },
+};
+
+pub(crate) struct DatalogFlows {
+  /// The `subset_base` edges of the constraint graph.
+  edges: Relation<(Origin, Origin)>,
+
+  /// The origins reached from each abstract, local, or dangling origin.
+  /// Reachability from any other origin is computed on demand.
+  reaches: HashMap<Origin, HashSet<Origin>>,
+
+  /// `(Origin1, Origin2)` such that the signature allows `Origin1` to
+  /// flow into `Origin2`, only defined for abstract origins.
+  specified: HashSet<(Origin, Origin)>,
+
+  /// Abstract origins, i.e. placeholders and those in the return type.
+  abstract_origins: Vec<Origin>,
+
+  /// Origins of borrows that refer directly to body-owned memory.
+  local_sources: Vec<Origin>,
+
+  /// Local sources equivalent to a borrow invalidated at an exit point.
+  dangling_local_sources: Vec<Origin>,
+}
+
+/// Compute the reflexive transitive closure of `edges` starting
+/// from `nodes`, as the set of origins reached by each node.
+///
+/// ```text
+/// reaches(Origin, Origin) :- node(Origin).
+///
+/// reaches(Origin1, Origin3) :-
+///   reaches(Origin1, Origin2),
+///   edge(Origin2, Origin3).
+/// ```
+fn reachable_from(
+  nodes: impl IntoIterator<Item = Origin>,
+  edges: &Relation<(Origin, Origin)>,
+) -> HashMap<Origin, HashSet<Origin>> {
+  let mut iteration = Iteration::new();
+
+  // Keyed by the target, i.e. `(Origin2, Origin1)`.
+  let reached_by = iteration.variable::<(Origin, Origin)>("reached_by");
+  reached_by.extend(nodes.into_iter().map(|o| (o, o)));
+
+  while iteration.changed() {
+    // reaches(Origin1, Origin3) :-
+    //   reaches(Origin1, Origin2),
+    //   edge(Origin2, Origin3).
+    reached_by.from_join(&reached_by, edges, |_o2, &o1, &o3| (o3, o1));
+  }
+
+  let mut reaches: HashMap<Origin, HashSet<Origin>> = HashMap::default();
+  for &(target, source) in reached_by.complete().iter() {
+    reaches.entry(source).or_default().insert(target);
+  }
+  reaches
+}
+
+impl DatalogFlows {
+  pub(crate) fn compute(
+    ctxt: &PermissionsCtxt,
+    constraints: &[(Origin, Origin)],
+    vertices: &HashSet<Origin>,
+  ) -> Self {
+    let abstract_origins = abstract_origins(ctxt, vertices);
+    let local_sources = local_source_origins(ctxt).collect::<Vec<_>>();
+    let dangling = dangling_origins(ctxt).collect::<Vec<_>>();
+
+    // Only the reachability from these sources is ever queried in bulk,
+    // computing it for all vertices would be quadratic in the body size.
+    let sources = abstract_origins
+      .iter()
+      .copied()
+      .chain(local_sources.iter().copied())
+      .chain(dangling.iter().copied())
+      .collect::<HashSet<_>>();
+
+    let edges = Relation::from_iter(constraints.iter().copied());
+    let reaches = reachable_from(sources, &edges);
+
+    let source_reaches = |o1: Origin, o2: Origin| {
+      o1 == o2 || reaches.get(&o1).is_some_and(|r| r.contains(&o2))
+    };
+    let equivalent =
+      |o1: Origin, o2: Origin| source_reaches(o1, o2) && source_reaches(o2, o1);
+
+    // We filter the placeholders because the `known_placeholder_subset`
+    // contains a top and bottom of the abstract lattice, but we only care
+    // about those that actually appear in the body.
+    let known_edges = ctxt
+      .polonius_input_facts
+      .known_placeholder_subset
+      .iter()
+      .copied()
+      .filter(|(f, t)| vertices.contains(f) && vertices.contains(t));
+
+    let equivalent_edges = abstract_origins
+      .iter()
+      .flat_map(|&a| abstract_origins.iter().map(move |&b| (a, b)))
+      .filter(|&(a, b)| a != b && equivalent(a, b));
+
+    let specified = reachable_from(
+      abstract_origins.iter().copied(),
+      &Relation::from_iter(known_edges.chain(equivalent_edges)),
+    )
+    .into_iter()
+    .flat_map(|(a, reached)| reached.into_iter().map(move |b| (a, b)))
+    .collect::<HashSet<_>>();
+
+    let dangling_local_sources = local_sources
+      .iter()
+      .copied()
+      .filter(|&l| dangling.iter().any(|&d| equivalent(l, d)))
+      .collect::<Vec<_>>();
+
+    log::debug!(
+      "Datalog flows: {} reachable pairs, {} specified pairs",
+      reaches.values().map(HashSet::len).sum::<usize>(),
+      specified.len()
+    );
+
+    DatalogFlows {
+      edges,
+      reaches,
+      specified,
+      abstract_origins,
+      local_sources,
+      dangling_local_sources,
+    }
+  }
+
+  /// The origins reachable from `origin`, including itself.
+  fn reachable(&self, origin: Origin) -> Cow<'_, HashSet<Origin>> {
+    match self.reaches.get(&origin) {
+      Some(reached) => Cow::Borrowed(reached),
+      None => Cow::Owned(
+        reachable_from([origin], &self.edges)
+          .remove(&origin)
+          .unwrap_or_default(),
+      ),
+    }
+  }
+
+  fn reaches(&self, from: Origin, to: Origin) -> bool {
+    from == to || self.reachable(from).contains(&to)
+  }
+
+  /// Abstract origins that could be contained in `origin`.
+  fn abstract_in(&self, origin: Origin) -> impl Iterator<Item = Origin> + '_ {
+    self
+      .abstract_origins
+      .iter()
+      .copied()
+      .filter(move |&a| self.reaches(a, origin))
+  }
+
+  /// Abstract sources that could be contained in `origin`.
+  pub(crate) fn abstract_sources(&self, origin: Origin) -> Vec<Origin> {
+    self.abstract_in(origin).collect()
+  }
+
+  /// Local sources that could be contained in `origin`.
+  pub(crate) fn local_sources(&self, origin: Origin) -> Vec<Origin> {
+    self
+      .local_sources
+      .iter()
+      .copied()
+      .filter(|&l| self.reaches(l, origin))
+      .collect()
+  }
+
+  /// Can data in the abstract origin `from` flow into `to`?
+  pub(crate) fn may_flow_into(&self, from: Origin, to: Origin) -> bool {
+    let from_reaches = self.reachable(from);
+    self
+      .abstract_in(to)
+      .any(|a| from_reaches.contains(&a) && self.reaches(a, from))
+  }
+
+  /// Abstract origins that flow from `from` into `to` without
+  /// a user-specified outlives relation.
+  pub(crate) fn missing_universal_constraints(
+    &self,
+    from: Origin,
+    to: Origin,
+  ) -> Vec<(Origin, Origin)> {
+    let abstract_to = self.abstract_in(to).collect::<Vec<_>>();
+    self
+      .abstract_in(from)
+      .cartesian_product(abstract_to)
+      .filter(|pair| !self.specified.contains(pair))
+      .collect()
+  }
+
+  /// Get the specific kind of flow edge that connects `from` and `to`.
+  pub(crate) fn flow_kind(&self, from: Origin, to: Origin) -> FlowEdgeKind {
+    let to_reaches = self.reachable(to);
+
+    // Data can always flow within the same component.
+    if self.reaches(from, to) && to_reaches.contains(&from) {
+      return FlowEdgeKind::Ok;
+    }
+
+    // A local value can never flow into an abstract region.
+    let from_contains_local = !self.local_sources(from).is_empty();
+    let to_is_abstract = self
+      .abstract_origins
+      .iter()
+      .any(|&a| self.reaches(a, to) && to_reaches.contains(&a));
+    if from_contains_local && to_is_abstract {
+      return FlowEdgeKind::LocalOutlivesUniversal;
+    }
+
+    // All abstract regions in `from` must be known to outlive
+    // those in `to`.
+    if !self.missing_universal_constraints(from, to).is_empty() {
+      return FlowEdgeKind::MissingUniversalConstraint;
+    }
+
+    // If `from` is flowing a dangling pointer we would always consider this an error.
+    if self
+      .dangling_local_sources
+      .iter()
+      .any(|&l| self.reaches(l, from))
+    {
+      return FlowEdgeKind::LocalInvalidatedAtExit;
+    }
+
+    FlowEdgeKind::Ok
+  }
+}
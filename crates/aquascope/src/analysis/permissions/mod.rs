@@ -1,8 +1,9 @@
 //! Aquascope permissions analysis.
 
 mod context;
+pub mod facts;
 pub(crate) mod flow;
-// pub(crate) mod flow_datalog;
+pub(crate) mod flow_datalog;
 mod output;
 
 pub mod utils;
@@ -18,17 +19,53 @@ pub use output::{Output, compute};
 use polonius_engine::FactTypes;
 use rustc_borrowck::consumers::RustcFacts;
 use rustc_data_structures::fx::FxHashMap;
+use rustc_hir::def_id::DefId;
 use rustc_index::Idx;
-use rustc_middle::mir::Place;
+use rustc_middle::{
+  mir::Place,
+  ty::{self, Ty, TyCtxt},
+};
+use rustc_span::sym;
 use rustc_utils::source_map::range::CharRange;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
-use crate::analysis::{LoanKey, LoanRefined, MoveKey};
+use crate::analysis::{
+  LoanKey, LoanRefined, MoveKey, ir_mapper::region_name::RegionName,
+};
 
 fluid_let!(pub static ENABLE_FLOW_PERMISSIONS: bool);
 pub const ENABLE_FLOW_DEFAULT: bool = false;
 
+fluid_let!(pub static FLOW_ENGINE: FlowEngine);
+pub const FLOW_ENGINE_DEFAULT: FlowEngine = FlowEngine::Imperative;
+
+/// The implementation used to classify region flows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Hash)]
+pub enum FlowEngine {
+  /// Graph algorithms over the SCCs of the constraint graph.
+  Imperative,
+
+  /// Datalog rules over the origins, see [`flow_datalog`].
+  Datalog,
+
+  /// Run both engines, use the imperative results, and
+  /// log any constraint where the two disagree.
+  CrossCheck,
+}
+
+impl std::str::FromStr for FlowEngine {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "Imperative" => Ok(Self::Imperative),
+      "Datalog" => Ok(Self::Datalog),
+      "CrossCheck" => Ok(Self::CrossCheck),
+      _ => Err(format!("Could not parse: {s}")),
+    }
+  }
+}
+
 /// Permission facts in Aquascope, similar to [`RustcFacts`].
 #[derive(Copy, Clone, Debug)]
 pub struct AquascopeFacts;
@@ -131,6 +168,12 @@ pub struct PermissionsData {
   /// Is this place uninitialized?
   pub path_uninitialized: bool,
 
+  /// Is this place uninitialized on every path reaching it? Unlike a
+  /// place that is only maybe uninitialized, it can be initialized by
+  /// an assignment even if it isn't declared `mut`.
+  #[serde(skip)]
+  pub path_definitely_uninitialized: bool,
+
   /// Is the [`Place`] currently uninitialized due to a move?
   #[serde(skip_serializing_if = "Option::is_none")]
   pub path_moved: Option<MoveKey>,
@@ -142,6 +185,15 @@ pub struct PermissionsData {
   #[serde(skip_serializing_if = "Option::is_none")]
   /// Is a live loan removing `drop` permissions?
   pub loan_drop_refined: Option<LoanKey>,
+
+  /// Does the type allow writes through a shared reference?
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub interior_mutability: Option<InteriorMutability>,
+
+  /// Is the [`Place`] reached through a raw pointer? If so, the
+  /// permissions are not checked by the compiler.
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pub unchecked: bool,
 }
 
 impl PermissionsData {
@@ -178,6 +230,131 @@ impl PermissionsData {
     let drop = self.type_droppable && read && self.loan_drop_refined.is_none();
     Permissions { read, write, drop }
   }
+
+  /// Like [`permissions_ignore_liveness`](Self::permissions_ignore_liveness),
+  /// but a readable path with [`InteriorMutability`] is also writeable.
+  ///
+  /// These writes are not checked by the compiler, instead they
+  /// are checked dynamically, e.g. `RefCell::borrow_mut` panics
+  /// if the value is already borrowed.
+  pub fn permissions_with_dynamic_writes(&self) -> Permissions {
+    let perms = self.permissions_ignore_liveness();
+    Permissions {
+      write: perms.write || (perms.read && self.interior_mutability.is_some()),
+      ..perms
+    }
+  }
+
+  /// Like [`permissions_ignore_liveness`](Self::permissions_ignore_liveness),
+  /// but for a path overwritten by an assignment.
+  ///
+  /// An assignment doesn't read the path, so a path that was never
+  /// initialized can be written even if it isn't declared `mut`:
+  ///
+  /// ```ignore
+  /// let s;
+  /// s = String::new();
+  /// ```
+  ///
+  /// A moved path can only be reinitialized if it is declared `mut`,
+  /// as can a path that is initialized on only some paths:
+  ///
+  /// ```ignore
+  /// let s;
+  /// if c { s = 1; }
+  /// s = 2; // E0384
+  /// ```
+  pub fn permissions_for_overwrite(&self) -> Permissions {
+    let perms = self.permissions_ignore_liveness();
+    let initializing =
+      self.path_definitely_uninitialized && self.path_moved.is_none();
+    let write = (self.type_writeable || initializing)
+      && !self.loan_refined.is_write_refined();
+    Permissions { write, ..perms }
+  }
+}
+
+/// Standard library types that permit mutation through a shared reference.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, TS)]
+#[ts(export)]
+pub enum InteriorMutability {
+  Cell,
+  RefCell,
+  Mutex,
+  RwLock,
+}
+
+impl InteriorMutability {
+  /// The interior mutability of `ty`, looking through references
+  /// and the smart pointers `Box`, `Rc`, and `Arc`.
+  pub fn of_ty<'tcx>(tcx: TyCtxt<'tcx>, mut ty: Ty<'tcx>) -> Option<Self> {
+    loop {
+      if let Some(boxed) = ty.boxed_ty() {
+        ty = boxed;
+        continue;
+      }
+
+      match ty.kind() {
+        ty::Ref(_, inner, _) => ty = *inner,
+        ty::Adt(def, args) => {
+          return match tcx.get_diagnostic_name(def.did())? {
+            sym::Rc | sym::Arc => Self::of_ty(tcx, args.type_at(0)),
+            _ => Self::of_adt(tcx, def.did()),
+          };
+        }
+        _ => return None,
+      }
+    }
+  }
+
+  fn of_adt(tcx: TyCtxt, def_id: DefId) -> Option<Self> {
+    match tcx.get_diagnostic_name(def_id)? {
+      sym::Cell => Some(Self::Cell),
+      sym::RefCell => Some(Self::RefCell),
+      sym::Mutex => Some(Self::Mutex),
+      sym::RwLock => Some(Self::RwLock),
+      _ => None,
+    }
+  }
+
+  /// The interior mutability written to by the method `def_id`, e.g.
+  /// `RefCell::borrow_mut`, however the method is called: by method call
+  /// syntax, through an auto-deref of an `Rc`, or by path.
+  pub fn of_write_method(tcx: TyCtxt, def_id: DefId) -> Option<Self> {
+    let impl_id = tcx.impl_of_assoc(def_id)?;
+    let ty::Adt(def, _) = tcx
+      .type_of(impl_id)
+      .instantiate_identity()
+      .skip_norm_wip()
+      .kind()
+    else {
+      return None;
+    };
+    let interior_mutability = Self::of_adt(tcx, def.did())?;
+    interior_mutability
+      .is_write_method(tcx.item_name(def_id).as_str())
+      .then_some(interior_mutability)
+  }
+
+  /// Does the method `name` write to the inner value?
+  fn is_write_method(self, name: &str) -> bool {
+    match self {
+      Self::Cell => {
+        matches!(name, "set" | "replace" | "swap" | "take" | "update")
+      }
+      Self::RefCell => matches!(
+        name,
+        "borrow_mut"
+          | "try_borrow_mut"
+          | "replace"
+          | "replace_with"
+          | "swap"
+          | "take"
+      ),
+      Self::Mutex => matches!(name, "lock" | "try_lock"),
+      Self::RwLock => matches!(name, "write" | "try_write"),
+    }
+  }
 }
 
 /// A permissions refiner. [`Loan`]s and moves can refine permissions.
@@ -194,6 +371,15 @@ pub enum Refiner {
 pub struct RefinementRegion {
   pub refiner_point: Refiner,
   pub refined_ranges: Vec<CharRange>,
+
+  /// Ranges where a two-phase loan is reserved but not yet activated.
+  /// These are always a subset of the `refined_ranges`.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub reserved_ranges: Vec<CharRange>,
+
+  /// Source-level name of the region a loan lives for.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub region_name: Option<RegionName>,
 }
 
 /// Permissions data *forall* places in the body under analysis.
@@ -212,6 +398,13 @@ impl Permissions {
       drop: false,
     }
   }
+
+  /// Are all of the permissions in `self` also in `other`?
+  pub fn is_subset(self, other: Permissions) -> bool {
+    (!self.read || other.read)
+      && (!self.write || other.write)
+      && (!self.drop || other.drop)
+  }
 }
 
 impl std::fmt::Debug for Permissions {
@@ -18,14 +18,14 @@ use rustc_hir::{BodyId, Mutability};
 use rustc_index::IndexVec;
 use rustc_middle::{
   mir::{Place, ProjectionElem},
-  ty::{TyCtxt, TypingEnv},
+  ty::{self, TyCtxt, TypingEnv},
 };
 use rustc_mir_dataflow::move_paths::MoveData;
 use rustc_utils::{BodyExt, PlaceExt};
 
 use super::{
-  AquascopeFacts, ENABLE_FLOW_DEFAULT, ENABLE_FLOW_PERMISSIONS, Loan, Move,
-  Path, Point, context::PermissionsCtxt, flow,
+  AquascopeFacts, ENABLE_FLOW_DEFAULT, ENABLE_FLOW_PERMISSIONS,
+  InteriorMutability, Loan, Move, Path, Point, context::PermissionsCtxt, flow,
 };
 
 /// Aquascope permissions facts output.
@@ -51,6 +51,15 @@ where
   ///
   pub(crate) never_write: HashSet<T::Path>,
 
+  /// The type checking writes to a [`Path`] at runtime, for paths of a
+  /// type with interior mutability. This only depends on the type of the
+  /// path, so it's computed once rather than at each point.
+  pub(crate) interior_mutability: HashMap<T::Path, InteriorMutability>,
+
+  /// Paths reached through a raw pointer, whose permissions the
+  /// compiler never checks.
+  pub(crate) unchecked: HashSet<T::Path>,
+
   /// A [`Path`] whose read permissions are refined at [`Point`] due to an active [`Loan`].
   ///
   /// ```text
@@ -62,7 +71,8 @@ where
   /// cannot_read(Path, Loan, Point) :-
   ///    loan_conflicts_with(Loan, Path),
   ///    loan_live_at(Loan, Point),
-  ///    loan_mutable(Loan).
+  ///    loan_mutable(Loan),
+  ///    !loan_reserved_at(Loan, Point).
   /// ```
   ///
   pub(crate) loan_read_refined: HashMap<T::Point, HashMap<T::Path, T::Loan>>,
@@ -123,6 +133,26 @@ where
   pub(crate) path_maybe_uninitialized_on_entry:
     HashMap<T::Point, HashSet<T::Path>>,
 
+  /// A [`Path`] that may be (partly) initialized on [`Point`] entry.
+  ///
+  /// A path that is maybe uninitialized but not maybe initialized is
+  /// definitely uninitialized, e.g. a `let s;` not yet assigned.
+  ///
+  /// ```text
+  /// .decl path_maybe_initialized_on_entry(Point, Path)
+  ///
+  /// path_maybe_initialized_on_entry(Point1, Path) :-
+  ///    path_maybe_initialized_on_exit(Point0, Path)
+  ///    cfg_edge(Point0, Point1)
+  ///
+  /// path_maybe_initialized_on_entry(Point, PathParent) :-
+  ///    child_path(PathChild, PathParent),
+  ///    path_maybe_initialized_on_entry(PathChild, Point).
+  /// ```
+  ///
+  pub(crate) path_maybe_initialized_on_entry:
+    HashMap<T::Point, HashSet<T::Path>>,
+
   /// A [`Path`] that is moved on [`Point`] entry.
   ///
   /// move_refined(Path, Move, Point) :-
@@ -152,18 +182,47 @@ where
   /// ```
   ///
   pub(crate) move_live_at: HashMap<T::Point, Vec<Move>>,
+
+  /// A two-phase [`Loan`] that is reserved, but not yet activated, on [`Point`] entry.
+  ///
+  /// While reserved, a two-phase borrow behaves like a shared borrow:
+  /// the borrowed path may still be read, but not written or dropped.
+  ///
+  /// ```text
+  /// .decl loan_reserved_at(Loan, Point)
+  ///
+  /// loan_reserved_at(Loan, Point) :-
+  ///   loan_issued_at(_, Loan, Point),
+  ///   loan_activated_at(Loan, _).
+  ///
+  /// loan_reserved_at(Loan, Point1) :-
+  ///   loan_reserved_at(Loan, Point0),
+  ///   cfg_edge(Point0, Point1),
+  ///   !cleanup_point(Point1),
+  ///   loan_live_at(Loan, Point1),
+  ///   !loan_activated_at(Loan, Point1).
+  /// ```
+  ///
+  /// A reservation only spans points where the loan is live, so it
+  /// is always contained in the region refined by the loan.
+  ///
+  pub(crate) loan_reserved_at: HashMap<T::Point, HashSet<T::Loan>>,
 }
 
 impl Default for Output<AquascopeFacts> {
   fn default() -> Self {
     Output {
       never_write: HashSet::default(),
+      interior_mutability: HashMap::default(),
+      unchecked: HashSet::default(),
       loan_read_refined: HashMap::default(),
       loan_write_refined: HashMap::default(),
       loan_drop_refined: HashMap::default(),
       path_maybe_uninitialized_on_entry: HashMap::default(),
+      path_maybe_initialized_on_entry: HashMap::default(),
       move_refined: HashMap::default(),
       move_live_at: HashMap::default(),
+      loan_reserved_at: HashMap::default(),
     }
   }
 }
@@ -216,6 +275,20 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
         }),
     );
 
+  let path_maybe_initialized_on_exit: Relation<(Point, Path)> =
+    Relation::from_iter(
+      ctxt
+        .polonius_output
+        .path_maybe_initialized_on_exit
+        .iter()
+        .flat_map(|(point, paths)| {
+          paths.iter().map(|path| {
+            let path = ctxt.moveable_path_to_path(*path);
+            (*point, path)
+          })
+        }),
+    );
+
   // move_conflicts_with(Move, Path) :-
   //   moved_out(Move, Path)
   //
@@ -264,6 +337,28 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
       },
     ));
 
+  // .decl loan_activated_at(Loan, Point)
+  //
+  // Two-phase borrows (e.g., the autoref of `v` in `v.push(v.len())`)
+  // are reserved at their issuing point and only activated at a later use.
+  let loan_activated_at: HashMap<Loan, Point> = ctxt
+    .polonius_input_facts
+    .loan_issued_at
+    .iter()
+    .filter_map(|&(_origin, loan, _point)| {
+      let loc = ctxt.loan_activation_location(loan)?;
+      Some((loan, ctxt.location_to_point(loc)))
+    })
+    .collect::<HashMap<_, _>>();
+
+  let loan_live_at: Relation<(Loan, Point)> = Relation::from_iter(
+    ctxt
+      .polonius_output
+      .loan_live_at
+      .iter()
+      .flat_map(|(point, values)| values.iter().map(|loan| (*loan, *point))),
+  );
+
   // We only need iteration for crawling across child paths
   // Paths that are partially moved can not have R/O permissions,
   // thus, if a child path is uninitialized (moved or non-initialized),
@@ -272,7 +367,26 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
 
   let path_maybe_uninitialized_on_entry =
     iteration.variable::<(Path, Point)>("path_maybe_uninitialized_on_entry");
+  let path_maybe_initialized_on_entry =
+    iteration.variable::<(Path, Point)>("path_maybe_initialized_on_entry");
   let move_live_at = iteration.variable::<(Move, Point)>("move_live_at");
+  let loan_reserved_at =
+    iteration.variable::<(Loan, Point)>("loan_reserved_at");
+
+  // loan_reserved_at(Loan, Point) :-
+  //   loan_issued_at(_, Loan, Point),
+  //   loan_activated_at(Loan, _).
+  //
+  // Two-phase loans that are never activated are left out, they
+  // are treated as any other mutable loan.
+  loan_reserved_at.extend(
+    ctxt
+      .polonius_input_facts
+      .loan_issued_at
+      .iter()
+      .filter(|&&(_origin, loan, _point)| loan_activated_at.contains_key(&loan))
+      .map(|&(_origin, loan, point)| (loan, point)),
+  );
 
   // move_live_at(Move, Point) :-
   //   move_out(Move, Point).
@@ -292,6 +406,15 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
     |&_point1, &path, &point2| (path, point2),
   ));
 
+  // path_maybe_initialized_on_entry(Point1, Path) :-
+  //    path_maybe_initialized_on_exit(Point0, Path)
+  //    cfg_edge(Point0, Point1)
+  path_maybe_initialized_on_entry.insert(Relation::from_join(
+    &path_maybe_initialized_on_exit,
+    &cfg_edge,
+    |&_point1, &path, &point2| (path, point2),
+  ));
+
   while iteration.changed() {
     // move_live_at(Move, Point1) :-
     //   move_live_at(Move, Point0),
@@ -335,6 +458,26 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
       |&(path, _point1), &point2| (path, point2),
     );
 
+    // loan_reserved_at(Loan, Point1) :-
+    //   loan_reserved_at(Loan, Point0),
+    //   cfg_edge(Point0, Point1),
+    //   !cleanup_point(Point1),
+    //   loan_live_at(Loan, Point1),
+    //   !loan_activated_at(Loan, Point1).
+    loan_reserved_at.from_leapjoin(
+      &loan_reserved_at,
+      (
+        cfg_edge.extend_with(|&(_loan, point1)| point1),
+        loan_live_at.extend_with(|&(loan, _point1)| loan),
+        ValueFilter::from(|&(loan, _point1), &point2| {
+          let block = ctxt.point_to_location(point2).block;
+          !body.basic_blocks[block].is_cleanup
+            && loan_activated_at.get(&loan) != Some(&point2)
+        }),
+      ),
+      |&(loan, _point1), &point2| (loan, point2),
+    );
+
     // path_maybe_uninitialized_on_entry(PathParent, Point) :-
     //    ancestor_path(PathParent, PathChild),
     //    path_maybe_uninitialized_on_entry(PathChild, Point).
@@ -343,11 +486,31 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
       &child_path,
       |&_child, &point, &parent| (parent, point),
     );
+
+    // path_maybe_initialized_on_entry(PathParent, Point) :-
+    //    ancestor_path(PathParent, PathChild),
+    //    path_maybe_initialized_on_entry(PathChild, Point).
+    path_maybe_initialized_on_entry.from_join(
+      &path_maybe_initialized_on_entry,
+      &child_path,
+      |&_child, &point, &parent| (parent, point),
+    );
   }
 
   let path_maybe_uninitialized_on_entry =
     path_maybe_uninitialized_on_entry.complete();
+  let path_maybe_initialized_on_entry =
+    path_maybe_initialized_on_entry.complete();
   let move_live_at = move_live_at.complete();
+  let loan_reserved_at = loan_reserved_at.complete();
+  let loan_live_set = loan_live_at.iter().copied().collect::<HashSet<_>>();
+  // The issuing point seeds the reservation even if the loan isn't
+  // live there (yet), those points are not part of the reservation.
+  let loan_reserved_set = loan_reserved_at
+    .iter()
+    .copied()
+    .filter(|fact| loan_live_set.contains(fact))
+    .collect::<HashSet<(Loan, Point)>>();
 
   // NOTE: We need to shift the move liveness by one in the MIR. Move
   //       liveness is defined as a move being live *on point entry*,
@@ -376,6 +539,12 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
             return mutability == Mutability::Not;
           }
 
+          // Raw pointers also carry their mutability in the type, writing
+          // through a `*mut T` doesn't require the pointer itself be `mut`.
+          if let ty::RawPtr(_, mutability) = ty.kind() {
+            return *mutability == Mutability::Not;
+          }
+
           // In the above example of `*x`, example `x` could also be a
           // `Box`, or for a different base path an array. These cases
           // would require us to check the mutability binding of the local.
@@ -404,20 +573,16 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
     ),
   );
 
-  let loan_live_at: Relation<(Loan, Point)> = Relation::from_iter(
-    ctxt
-      .polonius_output
-      .loan_live_at
-      .iter()
-      .flat_map(|(point, values)| values.iter().map(|loan| (*loan, *point))),
-  );
-
   let loan_read_refined: Relation<(Path, Loan, Point)> =
     Relation::from_leapjoin(
       &loan_conflicts_with,
       (
         loan_live_at.extend_with(|&(loan, _path)| loan),
-        ValueFilter::from(|&(loan, _path), _point| ctxt.is_mutable_loan(loan)),
+        // A reserved two-phase loan does not (yet) remove read permissions.
+        ValueFilter::from(|&(loan, _path), &point| {
+          ctxt.is_mutable_loan(loan)
+            && !loan_reserved_set.contains(&(loan, point))
+        }),
       ),
       |&(loan, path), &point| (path, loan, point),
     );
@@ -445,7 +610,23 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
     .filter_map(|path| is_never_write(*path).then_some(*path))
     .collect::<HashSet<_>>();
 
+  let interior_mutability = paths
+    .iter()
+    .filter_map(|&path| {
+      let ty = ctxt.path_to_place(path).ty(&body.local_decls, tcx).ty;
+      InteriorMutability::of_ty(tcx, ty).map(|im| (path, im))
+    })
+    .collect::<HashMap<_, _>>();
+
+  let unchecked = paths
+    .iter()
+    .filter(|&&path| ctxt.is_through_raw_pointer(&ctxt.path_to_place(path)))
+    .copied()
+    .collect::<HashSet<_>>();
+
   ctxt.permissions_output.never_write = never_write;
+  ctxt.permissions_output.interior_mutability = interior_mutability;
+  ctxt.permissions_output.unchecked = unchecked;
 
   for &(path, point) in path_maybe_uninitialized_on_entry.iter() {
     ctxt
@@ -456,6 +637,15 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
       .insert(path);
   }
 
+  for &(path, point) in path_maybe_initialized_on_entry.iter() {
+    ctxt
+      .permissions_output
+      .path_maybe_initialized_on_entry
+      .entry(point)
+      .or_default()
+      .insert(path);
+  }
+
   for &(movep, point) in move_live_at.iter() {
     ctxt
       .permissions_output
@@ -465,6 +655,15 @@ pub fn derive_permission_facts(ctxt: &mut PermissionsCtxt) {
       .push(movep);
   }
 
+  for &(loan, point) in &loan_reserved_set {
+    ctxt
+      .permissions_output
+      .loan_reserved_at
+      .entry(point)
+      .or_default()
+      .insert(loan);
+  }
+
   macro_rules! insert_facts {
     ($input:expr, $field:expr) => {
       for &(path, loan, point) in $input.iter() {
@@ -0,0 +1,217 @@
+//! Focusing the permission steps on paths and lines of interest.
+//!
+//! The steps of a large body quickly become hard to read, and costly
+//! to send around. A [`StepFocus`] prunes them down to the places whose
+//! path matches one of its [`PathMatcher`]s, optionally on some lines
+//! only. Tables left empty are removed, as are lines without tables.
+
+use fluid_let::fluid_let;
+use regex::Regex;
+
+use super::PermissionsLineDisplay;
+
+fluid_let!(pub static STEP_FOCUS: StepFocus);
+
+/// Matches the path of a place, e.g. `v` or `(*v)[_]`.
+#[derive(Clone, Debug)]
+pub enum PathMatcher {
+  /// Matches exactly the given path.
+  Literal(String),
+
+  /// Matches the paths the regex matches. When parsed from a string,
+  /// i.e. one prefixed with `re:`, the regex is anchored and has to
+  /// match the path in full.
+  Regex(Regex),
+}
+
+impl PathMatcher {
+  pub fn is_match(&self, path: &str) -> bool {
+    match self {
+      PathMatcher::Literal(literal) => literal == path,
+      PathMatcher::Regex(regex) => regex.is_match(path),
+    }
+  }
+}
+
+impl std::str::FromStr for PathMatcher {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let Some(regex) = s.strip_prefix("re:") else {
+      return Ok(PathMatcher::Literal(s.to_owned()));
+    };
+    // Anchor the alternatives, `v|w` should match neither `vw` nor `(*v)`.
+    Regex::new(&format!("^(?:{regex})$"))
+      .map(PathMatcher::Regex)
+      .map_err(|e| format!("Could not parse: {e}"))
+  }
+}
+
+/// An inclusive range of source lines, numbered from 1.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LineRange {
+  pub start: usize,
+  pub end: usize,
+}
+
+impl LineRange {
+  /// Whether the range contains the 0-based `line` of a [`CharPos`].
+  ///
+  /// [`CharPos`]: rustc_utils::source_map::range::CharPos
+  pub fn contains(&self, line: usize) -> bool {
+    (self.start ..= self.end).contains(&(line + 1))
+  }
+}
+
+impl std::str::FromStr for LineRange {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let parse = |n: &str| {
+      n.trim()
+        .parse::<usize>()
+        .map_err(|_| format!("Could not parse: {s}"))
+    };
+    let (start, end) = match s.split_once('-') {
+      Some((start, end)) => (parse(start)?, parse(end)?),
+      None => (parse(s)?, parse(s)?),
+    };
+    if start == 0 || end < start {
+      return Err(format!("Could not parse: {s}"));
+    }
+    Ok(LineRange { start, end })
+  }
+}
+
+/// The paths and lines the permission steps are restricted to.
+///
+/// The default focus keeps every step.
+#[derive(Clone, Debug, Default)]
+pub struct StepFocus {
+  /// Keep the places matched by any of these, all of them if empty.
+  pub paths: Vec<PathMatcher>,
+
+  /// Keep the steps shown on these lines.
+  pub lines: Option<LineRange>,
+}
+
+impl StepFocus {
+  pub fn is_empty(&self) -> bool {
+    self.paths.is_empty() && self.lines.is_none()
+  }
+
+  fn includes_path(&self, path: &str) -> bool {
+    self.paths.is_empty() || self.paths.iter().any(|m| m.is_match(path))
+  }
+
+  /// Remove the parts of `steps` outside of the focus.
+  pub fn prune(&self, steps: &mut Vec<PermissionsLineDisplay>) {
+    if self.is_empty() {
+      return;
+    }
+
+    steps.retain_mut(|display| {
+      if self
+        .lines
+        .is_some_and(|lines| !lines.contains(display.location.start.line))
+      {
+        return false;
+      }
+
+      display.state.retain_mut(|table| {
+        table.state.retain(|(path, _)| self.includes_path(path));
+        table
+          .raw_pointers
+          .retain(|step| self.includes_path(&step.place));
+        table
+          .held_across_await
+          .retain(|step| self.includes_path(&step.place));
+        !(table.state.is_empty()
+          && table.raw_pointers.is_empty()
+          && table.held_across_await.is_empty())
+      });
+
+      !display.state.is_empty()
+    });
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    analysis::{AquascopeAnalysis, compute_permission_steps},
+    test_utils as tu,
+  };
+
+  #[test]
+  fn path_matchers() {
+    let regex = "re:v|v\\[.*\\]".parse::<PathMatcher>().unwrap();
+    assert!(regex.is_match("v"));
+    assert!(regex.is_match("v[_]"));
+    assert!(!regex.is_match("(*v)"));
+    assert!(!regex.is_match("vs"));
+
+    let literal = "v[_]".parse::<PathMatcher>().unwrap();
+    assert!(literal.is_match("v[_]"));
+    assert!(!literal.is_match("v[0]"));
+    assert!(!literal.is_match("v"));
+  }
+
+  #[test]
+  fn line_ranges() {
+    let range = "2-4".parse::<LineRange>().unwrap();
+    assert_eq!(range, LineRange { start: 2, end: 4 });
+    assert!(!range.contains(0));
+    assert!(range.contains(1));
+    assert!(range.contains(3));
+    assert!(!range.contains(4));
+
+    assert_eq!("3".parse::<LineRange>(), Ok(LineRange { start: 3, end: 3 }));
+    assert!("0-2".parse::<LineRange>().is_err());
+    assert!("4-2".parse::<LineRange>().is_err());
+  }
+
+  #[test]
+  fn prune_keeps_tables_with_raw_pointers() {
+    let source = r"
+fn take(_p: *mut i32) {}
+fn main() {
+  let mut x = 1;
+  let mut y = 2;
+  take(&mut x as *mut i32);
+  x += y;
+  y += 1;
+}
+";
+    let focus = StepFocus {
+      paths: vec![PathMatcher::Literal("x".into())],
+      lines: Some(LineRange { start: 5, end: 7 }),
+    };
+    tu::compile_normal(source, |tcx| {
+      let mut lines = Vec::new();
+      let mut raw_pointers = Vec::new();
+      tu::for_each_body(tcx, |body_id, _| {
+        let analysis = AquascopeAnalysis::new(tcx, body_id);
+        let mut steps =
+          compute_permission_steps(&analysis, &StepFocus::default()).unwrap();
+        focus.prune(&mut steps);
+        for display in steps {
+          lines.push(display.location.start.line);
+          for table in display.state {
+            for (path, _) in &table.state {
+              assert_eq!(path, "x", "{:?}", display.location);
+            }
+            raw_pointers
+              .extend(table.raw_pointers.into_iter().map(|p| p.place));
+          }
+        }
+      });
+
+      // Line 4 only steps through `y`, and line 7 is out of focus.
+      assert!(
+        lines.iter().all(|line| (5 ..= 6).contains(line)),
+        "{lines:?}"
+      );
+      assert_eq!(raw_pointers, ["x"]);
+    });
+  }
+}
@@ -22,6 +22,10 @@
 //! 3. Entering a block, potentially from a conditional branch
 //!    which can cause liveness permissions changes.
 //!
+//! When stepping with [`StepGranularity::Expression`] steps are also
+//! inserted after each sub-expression, e.g. the arguments of a call,
+//! as long as the sub-expression isn't evaluated conditionally.
+//!
 //! For most of the process, the [`SegmentedMirBuilder`] handles all the
 //! tough work of making sure steps are valid. There are a few cases when
 //! the HIR knows more about the structure of a program and they all have to
@@ -55,9 +59,25 @@
 //! of the branch, then in the `Some` case `n` is _already bound_. We can use info
 //! at the HIR level to find this micro adjustment which computes the branch target
 //! as being after the code initializing all bound variables in a match pattern.
+//!
+//! An arm with a guard branches once more, on the result of the guard, either
+//! entering the arm body or falling through to the next arm. Guard bindings
+//! are references into the scrutinee that only live while the guard runs,
+//! so there is a step before the guard, showing them, and one after it.
+//!
+//! Early exits, a `return`, `break` or `continue`, leave the control flow
+//! the HIR is nested in. The step of an exit jumps to its target and ends
+//! the path it's on, the steps following a branch continue from the paths
+//! which didn't exit. A `let ... else` is a branch of its own, on whether
+//! the pattern matches, its `else` block always exits. A plain `loop` and
+//! a labeled block are only left by a `break`, so the steps after them
+//! continue from where the `break`s jump to.
 
 use anyhow::{Result, anyhow};
-use rustc_data_structures::{self, fx::FxHashMap as HashMap};
+use rustc_data_structures::{
+  self,
+  fx::{FxHashMap as HashMap, FxHashSet as HashSet},
+};
 use rustc_hir::{
   self as hir, BodyId, HirId,
   intravisit::{self, Visitor as HirVisitor},
@@ -68,7 +88,7 @@ use rustc_middle::{
   ty::TyCtxt,
 };
 use rustc_span::Span;
-use rustc_utils::SpanExt;
+use rustc_utils::{BodyExt, SpanExt};
 
 use super::{segmented_mir::*, table_builder::*, *};
 use crate::analysis::ir_mapper::{GatherDepth, IRMapper};
@@ -96,7 +116,10 @@ where
   /// Stack of the current branch entry points, used
   /// for hinting path steps to the `SegmentedMir`.
   current_branch_start: Vec<Location>,
+  /// Where the `break`s out of a loop, or labeled block, jump to.
+  break_targets: HashMap<HirId, Location>,
   mir_segments: SegmentedMirBuilder<'a, 'tcx>,
+  granularity: StepGranularity,
 }
 
 /// Makes calling functions on the SegmentedMir easier.
@@ -148,6 +171,7 @@ impl<'a, 'tcx: 'a> HirStepPoints<'a, 'tcx> {
     body: &'tcx Body<'tcx>,
     body_id: BodyId,
     ir_mapper: &'a IRMapper<'tcx>,
+    granularity: StepGranularity,
   ) -> Result<Self> {
     let mir_segments = SegmentedMirBuilder::make(ir_mapper);
     let start_loc = mir::START_BLOCK.start_location();
@@ -162,7 +186,9 @@ impl<'a, 'tcx: 'a> HirStepPoints<'a, 'tcx> {
       start_loc,
       locals_at_scope: HashMap::default(),
       current_branch_start: Vec::default(),
+      break_targets: HashMap::default(),
       mir_segments,
+      granularity,
     })
   }
 
@@ -210,7 +236,12 @@ impl<'a, 'tcx: 'a> HirStepPoints<'a, 'tcx> {
       locals_at_scope: self.locals_at_scope,
     };
 
-    Ok(finalizer.finalize_body(self.start_loc, body_span, mode))
+    Ok(finalizer.finalize_body(
+      self.start_loc,
+      body_span,
+      mode,
+      self.granularity,
+    ))
   }
 
   // Used for tracking path hints of the current branches.
@@ -296,13 +327,49 @@ impl<'a, 'tcx: 'a> HirStepPoints<'a, 'tcx> {
     // case of a more complex condition expression, splitting this
     // first will result in a split location closest to the `SwitchInt`.
     self.visit_expr(cnd);
-    let Some(cnd_exit) = self.get_node_exit(cnd.hir_id).or_else(|| {
-      log::warn!(
-        "EXPR condition has no exit {} looking at expr entry",
-        self.prettify_node(cnd.hir_id)
-      );
-      self.get_node_entry(expr.hir_id)
-    }) else {
+
+    // A diverging condition, e.g. `if (return) { .. }`, never reaches
+    // the branch and everything after it is unreachable.
+    if self
+      .tcx
+      .typeck(cnd.hir_id.owner.def_id)
+      .expr_ty_opt(cnd)
+      .is_some_and(|ty| ty.is_never())
+    {
+      log::debug!("EXPR condition diverges {}", self.prettify_node(cnd.hir_id));
+      return None;
+    }
+
+    // The temporaries of a `while` condition are dropped on both paths
+    // out of its `switchInt`, so the condition "exits" after the loop.
+    // An exit that doesn't lead to the switch of the loop lies past the
+    // branch and can't open it.
+    let is_loop = matches!(expr.kind, hir::ExprKind::Loop(..));
+    let switches_within = |location: Location| {
+      self
+        .mir_segments
+        .find_next_switchint(location)
+        .map(|location| self.body.location_to_hir_id(location))
+        .is_some_and(|switch_id| {
+          self
+            .tcx
+            .hir_parent_id_iter(switch_id)
+            .any(|id| id == expr.hir_id)
+        })
+    };
+
+    let Some(cnd_exit) = self
+      .get_node_exit(cnd.hir_id)
+      .filter(|&location| !is_loop || switches_within(location))
+      .or_else(|| self.condition_switch(cnd))
+      .or_else(|| {
+        log::warn!(
+          "EXPR condition has no exit {} looking at expr entry",
+          self.prettify_node(cnd.hir_id)
+        );
+        self.get_node_entry(expr.hir_id)
+      })
+    else {
       log::warn!("cannot do EXPR prelude, aborting");
       return None;
     };
@@ -319,6 +386,32 @@ impl<'a, 'tcx: 'a> HirStepPoints<'a, 'tcx> {
     Some(cnd_exit)
   }
 
+  /// The last `switchInt` of a condition. Temporaries of the condition
+  /// are dropped on both paths out of it, e.g. those of a `matches!`,
+  /// in which case the condition as a whole has no exit.
+  fn condition_switch(&self, cnd: &hir::Expr) -> Option<Location> {
+    let mapper = self.ir_mapper;
+    let body = mapper.cleaned_graph.body();
+    let switches = mapper
+      .get_mir_locations(cnd.hir_id, GatherDepth::Nested)?
+      .values()
+      .filter(|location| {
+        let data = &body.basic_blocks[location.block];
+        location.statement_index == data.statements.len()
+          && matches!(
+            data.terminator().kind,
+            mir::TerminatorKind::SwitchInt { .. }
+          )
+      })
+      .collect::<Vec<_>>();
+
+    switches.iter().copied().find(|&location| {
+      switches
+        .iter()
+        .all(|&other| mapper.ldominates(other, location))
+    })
+  }
+
   /// Close the entire branching expression which had the condition exit.
   ///
   /// Here, the given expression should be the _entire_ `EK::If` or `EK::Match`.
@@ -352,6 +445,211 @@ impl<'a, 'tcx: 'a> HirStepPoints<'a, 'tcx> {
     }
   }
 
+  /// Inserts a step after a sub-expression, when stepping by expression.
+  ///
+  /// Only steps continuing the straight-line control flow are inserted.
+  /// An expression evaluated conditionally, e.g. the right operand of
+  /// `&&`, is stepped over by the statement holding it.
+  fn insert_expr_step(&mut self, expr: &'tcx hir::Expr) {
+    if self.granularity != StepGranularity::Expression
+      || expr.span.from_expansion()
+      || matches!(expr.kind, hir::ExprKind::Block(..))
+    {
+      return;
+    }
+
+    if let Some(exit) = self.get_node_exit(expr.hir_id) {
+      invoke_internal!(
+        self,
+        insert_if_linear,
+        exit,
+        self.get_path_hint(),
+        self.span_of(expr.hir_id)
+      );
+    }
+  }
+
+  /// Whether `expr` jumps out of the enclosing control flow, as written
+  /// in the source. The jumps of desugarings, e.g. the `break` ending a
+  /// `while` loop, are stepped over with the construct they belong to.
+  fn is_early_exit(expr: &hir::Expr) -> bool {
+    match expr.kind {
+      hir::ExprKind::Ret(_) => true,
+      hir::ExprKind::Break(..) | hir::ExprKind::Continue(_) => {
+        expr.span.desugaring_kind().is_none()
+      }
+      _ => false,
+    }
+  }
+
+  /// The location of the body's return.
+  fn return_location(&self) -> Option<Location> {
+    use itertools::Itertools;
+    self.body.all_returns().exactly_one().ok()
+  }
+
+  /// The location where the steps through the body `hir_id` end.
+  fn body_exit(&self, hir_id: HirId) -> Option<Location> {
+    // The final drops and return of a closure (or coroutine) are attributed
+    // to the closure expression rather than the body, so the body ends at
+    // the return. After an early return the body doesn't have a single
+    // exit either, the paths through it only meet at the return.
+    if hir_id != self.body_value_id() {
+      self.get_node_exit(hir_id)
+    } else if self.tcx.is_closure_like(self.body.source.def_id()) {
+      self.return_location()
+    } else {
+      self
+        .get_node_exit(hir_id)
+        .or_else(|| self.return_location())
+    }
+  }
+
+  /// Inserts the step of an early exit, from the last step before
+  /// it to where it jumps to.
+  fn insert_exit_step(&mut self, expr: &'tcx hir::Expr) {
+    let location = self
+      .ir_mapper
+      .get_mir_locations(expr.hir_id, GatherDepth::Nested)
+      .and_then(|mir_order| mir_order.exit_location());
+    // A `return` jumps past the drops of the body's locals, which are
+    // attributed to the body rather than the `return` itself.
+    let target = match expr.kind {
+      hir::ExprKind::Ret(_) => self.return_location(),
+      hir::ExprKind::Break(
+        hir::Destination {
+          target_id: Ok(target_id),
+          ..
+        },
+        _,
+      ) => self.break_target(expr, target_id),
+      _ => self.get_node_exit(expr.hir_id),
+    };
+    let (Some(location), Some(target)) = (location, target) else {
+      log::warn!(
+        "early exit doesn't have an exit location {}",
+        self.prettify_node(expr.hir_id)
+      );
+      return;
+    };
+
+    if let hir::ExprKind::Break(destination, _) = expr.kind
+      && let Ok(target_id) = destination.target_id
+    {
+      self.break_targets.entry(target_id).or_insert(target);
+    }
+
+    let span = self.span_of(expr.hir_id);
+    invoke_internal!(self, insert_exit, location, target, span);
+  }
+
+  /// The location a `break` jumps to, the first location following it
+  /// which isn't part of the loop, or labeled block, `destination`.
+  fn break_target(
+    &self,
+    expr: &hir::Expr,
+    destination: HirId,
+  ) -> Option<Location> {
+    let leaves = |location: Location| {
+      let hir_id = self.body.location_to_hir_id(location);
+      hir_id != destination
+        && !self
+          .tcx
+          .hir_parent_id_iter(hir_id)
+          .any(|id| id == destination)
+    };
+
+    let mut location = self.get_node_exit(expr.hir_id)?;
+    let mut visited = HashSet::default();
+    while !leaves(location) {
+      if !visited.insert(location) {
+        return None;
+      }
+      location = self.ir_mapper.cleaned_graph.location_successor(location)?;
+    }
+    Some(location)
+  }
+
+  /// Continue the steps after the loop, or labeled block, `hir_id`
+  /// from where its `break`s jump to.
+  fn continue_after_breaks(&mut self, hir_id: HirId) {
+    if let (Some(entry), Some(target)) = (
+      self.get_node_entry(hir_id),
+      self.break_targets.get(&hir_id).copied(),
+    ) {
+      invoke_internal!(self, continue_at, entry, target);
+    }
+  }
+
+  /// Step through a `let ... else`, a branch on whether the pattern
+  /// matches `init`. The `else` block has to diverge, so the steps
+  /// following the statement continue after the pattern's bindings.
+  fn handle_let_else(
+    &mut self,
+    stmt: &'tcx hir::Stmt,
+    local: &'tcx hir::LetStmt,
+    init: &'tcx hir::Expr,
+    els: &'tcx hir::Block,
+  ) {
+    let Some(init_exit) = self.expr_condition_prelude(init, init) else {
+      return;
+    };
+
+    let Some(els_entry) = self.get_node_entry(els.hir_id) else {
+      log::warn!(
+        "let-else block doesn't have entry {}",
+        self.prettify_node(els.hir_id)
+      );
+      return;
+    };
+
+    let mapper = self.ir_mapper;
+    let else_span = self.span_of(els.hir_id).shrink_to_lo();
+    let bound_span = self.span_of(local.pat.hir_id).shrink_to_hi();
+    let branch_id = invoke_internal!(
+      self,
+      open_branch,
+      init_exit,
+      move |to: &mut Location| {
+        if mapper.ldominates(*to, els_entry) {
+          *to = els_entry;
+          else_span
+        } else {
+          bound_span
+        }
+      }
+    );
+
+    self.push_branch_start(els_entry);
+    self.visit_block(els);
+    self.pop_branch_start(els_entry);
+
+    self.expr_condition_postlude(branch_id, stmt.hir_id);
+
+    // The pattern is tested before the split but only binds on the path
+    // that doesn't enter the `else` block, the step ends after the last
+    // location of the `let` on that path.
+    let bound = self
+      .ir_mapper
+      .get_mir_locations(local.hir_id, GatherDepth::Outer)
+      .and_then(|mir_order| {
+        let bindings = mir_order
+          .values()
+          .filter(|l| !mapper.dominates(els_entry.block, l.block))
+          .collect::<Vec<_>>();
+        bindings
+          .iter()
+          .find(|&&l| bindings.iter().all(|&other| mapper.ldominates(other, l)))
+          .copied()
+      })
+      .map(|l| mapper.cleaned_graph.location_successor(l).unwrap_or(l));
+
+    if let Some(exit) = bound {
+      let span = self.span_of(stmt.hir_id);
+      invoke_internal!(self, insert, exit, self.get_path_hint(), span);
+    }
+  }
+
   fn condition_produced_switchint(&self, expr: &'tcx hir::Expr) -> bool {
     if let Some(exit) = self.get_node_exit(expr.hir_id) {
       log::debug!(
@@ -441,6 +739,28 @@ impl<'a, 'tcx: 'a> HirStepPoints<'a, 'tcx> {
     self.expr_condition_postlude(branch_id, expr_id);
   }
 
+  /// Add the steps from the end of each iteration of the loop `loop_id`
+  /// back to its head, shown at the end of the loop body `body_id`.
+  fn insert_loop_back_edges(&mut self, loop_id: HirId, body_id: HirId) {
+    let Some(mir_order) = self
+      .ir_mapper
+      .get_mir_locations(loop_id, GatherDepth::Nested)
+    else {
+      log::warn!(
+        "loop doesn't have MIR locations {}",
+        self.prettify_node(loop_id)
+      );
+      return;
+    };
+
+    let blocks = mir_order
+      .values()
+      .map(|location| location.block)
+      .collect::<HashSet<_>>();
+    let span = self.span_of(body_id).shrink_to_hi();
+    invoke_internal!(self, insert_back_edges, &blocks, span);
+  }
+
   fn handle_expr_match(
     &mut self,
     expr: &'tcx hir::Expr,
@@ -452,33 +772,201 @@ impl<'a, 'tcx: 'a> HirStepPoints<'a, 'tcx> {
     let Some(cnd_exit) = self.expr_condition_prelude(cnd, expr) else {
       return;
     };
+
+    let switch = self.mir_segments.find_next_switchint(cnd_exit);
+
+    // A match whose patterns are all irrefutable, e.g. `|_| Some(2)`,
+    // doesn't switch at all. The next switch lies past the arms it
+    // enters, so they're stepped through without opening a branch.
+    let switches = switch.is_some_and(|switch| {
+      arms
+        .iter()
+        .filter_map(|arm| self.get_node_entry(arm.body.hir_id))
+        .all(|entry| self.ir_mapper.ldominates(switch, entry))
+    });
+    if !switches {
+      log::debug!("EXPR match doesn't switch {}", self.prettify_node(expr_id));
+      for arm in arms {
+        self.visit_arm(arm);
+      }
+      return;
+    }
+
+    // Without a switch on its discriminant, e.g. on `()`, a match is split
+    // by the switch of its first guard. A switch of a branch within that
+    // guard would then have to split the match.
+    let splits_in_guard = switch
+      .map(|location| self.body.location_to_hir_id(location))
+      .is_some_and(|switch_id| {
+        arms.iter().filter_map(|arm| arm.guard).any(|guard| {
+          switch_id != guard.hir_id
+            && self
+              .tcx
+              .hir_parent_id_iter(switch_id)
+              .any(|id| id == guard.hir_id)
+        })
+      });
+    if splits_in_guard {
+      report_unsupported!(
+        self,
+        "match guards with branches splitting the match are not yet supported {}",
+        self.prettify_node(expr_id)
+      );
+      return;
+    }
+
     let mapper = self.ir_mapper;
     let branch_id = invoke_internal!(
       self,
       open_branch,
       cnd_exit,
       move |to: &mut Location| {
-        entry_locs_to_spans
+        let reachable = entry_locs_to_spans
           .iter()
-          .find_map(|(&l, &span)| {
-            if mapper.ldominates(*to, l) {
-              // Update the location to be the entry of the arm.
-              *to = l;
-              Some(span)
-            } else {
-              None
-            }
+          .filter(|&(&l, _)| mapper.ldominates(*to, l))
+          .collect::<Vec<_>>();
+
+        // Falling through a guard, one arm leads to the others and
+        // the target enters that first arm.
+        let entered = reachable
+          .iter()
+          .find(|&&(&l, _)| {
+            reachable
+              .iter()
+              .all(|&(&other, _)| mapper.ldominates(l, other))
           })
-          .unwrap_or(Span::default())
+          .or(reachable.first());
+
+        match entered {
+          Some(&(&l, &span)) => {
+            // Update the location to be the entry of the arm.
+            *to = l;
+            span
+          }
+          None => Span::default(),
+        }
       }
     );
 
     for arm in arms {
-      self.visit_arm(arm);
+      match arm.guard {
+        Some(guard) => self.handle_guarded_arm(branch_id, arm, guard),
+        None => self.visit_arm(arm),
+      }
     }
 
     self.expr_condition_postlude(branch_id, expr_id);
   }
+
+  /// Step through a match arm with a guard, which is a branch nested in
+  /// the branch `match_id` of the match. The guard's `switchInt` either
+  /// enters the arm body or falls through to the next arm.
+  fn handle_guarded_arm(
+    &mut self,
+    match_id: BranchId,
+    arm: &'tcx hir::Arm,
+    guard: &'tcx hir::Expr,
+  ) {
+    let Some(arm_entry) = self.get_node_entry(arm.hir_id) else {
+      log::warn!(
+        "match-arm doesn't have entry {}",
+        self.prettify_node(arm.hir_id)
+      );
+      return;
+    };
+
+    self.push_branch_start(arm_entry);
+    self.handle_arm_guard(match_id, arm, guard);
+    self.pop_branch_start(arm_entry);
+  }
+
+  fn handle_arm_guard(
+    &mut self,
+    match_id: BranchId,
+    arm: &'tcx hir::Arm,
+    guard: &'tcx hir::Expr,
+  ) {
+    // The guard sees the pattern bindings by reference, these are
+    // bound before the guard is evaluated and end once it has been.
+    let Some(guard_entry) = self.get_node_entry(guard.hir_id) else {
+      log::warn!(
+        "match-arm guard doesn't have entry {}",
+        self.prettify_node(guard.hir_id)
+      );
+      return;
+    };
+    let span = self.span_of(arm.pat.hir_id).shrink_to_hi();
+    invoke_internal!(self, insert, guard_entry, self.get_path_hint(), span);
+
+    self.visit_expr(guard);
+
+    // The guard's temporaries die on both of its paths, so it has no exit
+    // of its own. The branch is rooted at the `switchInt` on its result,
+    // which a guard evaluated conditionally, e.g. `a && b`, doesn't have.
+    let Some(guard_exit) = self.guard_switch(guard, guard_entry) else {
+      report_unsupported!(
+        self,
+        "match arm guards without a single switch are not yet supported {}",
+        self.prettify_node(guard.hir_id)
+      );
+      return;
+    };
+    let span = self.span_of(guard.hir_id);
+    invoke_internal!(self, insert, guard_exit, self.get_path_hint(), span);
+
+    let Some(body_entry) = self.get_node_entry(arm.body.hir_id) else {
+      log::warn!(
+        "guarded arm body doesn't have entry {}",
+        self.prettify_node(arm.body.hir_id)
+      );
+      return;
+    };
+
+    let mapper = self.ir_mapper;
+    let enter_span = self.span_of(guard.hir_id).shrink_to_hi();
+    let fall_through_span = self.span_of(arm.hir_id).shrink_to_hi();
+    invoke_internal!(
+      self,
+      open_nested_branch,
+      match_id,
+      guard_exit,
+      move |to: &mut Location| {
+        if mapper.ldominates(*to, body_entry) {
+          *to = body_entry;
+          enter_span
+        } else {
+          fall_through_span
+        }
+      }
+    );
+
+    let span = self.span_of(arm.hir_id).shrink_to_lo();
+    invoke_internal!(self, insert, body_entry, self.get_path_hint(), span);
+    self.visit_expr(arm.body);
+  }
+
+  /// The `switchInt` on the result of `guard`, evaluated on every path
+  /// from the `guard_entry`.
+  fn guard_switch(
+    &self,
+    guard: &'tcx hir::Expr,
+    guard_entry: Location,
+  ) -> Option<Location> {
+    let mapper = self.ir_mapper;
+    let body = mapper.cleaned_graph.body();
+    mapper
+      .get_mir_locations(guard.hir_id, GatherDepth::Nested)?
+      .values()
+      .find(|&location| {
+        let data = &body.basic_blocks[location.block];
+        location.statement_index == data.statements.len()
+          && matches!(
+            data.terminator().kind,
+            mir::TerminatorKind::SwitchInt { .. }
+          )
+          && mapper.lpost_dominates(location, guard_entry)
+      })
+  }
 }
 
 impl<'a, 'tcx: 'a> HirVisitor<'tcx> for HirStepPoints<'a, 'tcx> {
@@ -490,7 +978,16 @@ impl<'a, 'tcx: 'a> HirVisitor<'tcx> for HirStepPoints<'a, 'tcx> {
 
   fn visit_body(&mut self, body: &hir::Body<'tcx>) {
     intravisit::walk_body(self, body);
-    self.insert_step_at_node_exit(body.value.hir_id);
+
+    let hir_id = body.value.hir_id;
+    if let Some(exit) = self.body_exit(hir_id) {
+      invoke_internal!(self, insert_end, exit, self.span_of(hir_id));
+    } else {
+      log::warn!(
+        "Body {} doesn't have an exit location.",
+        self.prettify_node(hir_id)
+      );
+    }
   }
 
   fn visit_block(&mut self, block: &hir::Block<'tcx>) {
@@ -503,7 +1000,9 @@ impl<'a, 'tcx: 'a> HirVisitor<'tcx> for HirStepPoints<'a, 'tcx> {
       log::debug!("BLOCK contains final EXPR");
 
       self.visit_expr(expr);
-      self.insert_step_at_node_exit(expr.hir_id);
+      if !Self::is_early_exit(expr) {
+        self.insert_step_at_node_exit(expr.hir_id);
+      }
     }
     invoke_internal!(self, close_scope, scope);
   }
@@ -528,17 +1027,64 @@ impl<'a, 'tcx: 'a> HirVisitor<'tcx> for HirStepPoints<'a, 'tcx> {
       }
     }
 
-    intravisit::walk_stmt(self, stmt);
+    match stmt.kind {
+      SK::Let(
+        local @ hir::LetStmt {
+          init: Some(init),
+          els: Some(els),
+          ..
+        },
+      ) => self.handle_let_else(stmt, local, init, els),
+      _ => intravisit::walk_stmt(self, stmt),
+    }
 
     // Close the scope before inserting the final steps.
     invoke_internal!(self, close_scope, scope);
 
+    // The arguments of an `async fn` are moved into the coroutine
+    // by desugared statements. Like the arguments of a regular
+    // function, they should come alive at the opening brace.
+    if let SK::Let(hir::LetStmt {
+      source: hir::LocalSource::AsyncFn,
+      ..
+    }) = stmt.kind
+    {
+      if let Some(exit) = self.get_node_exit(stmt.hir_id) {
+        let span = self.span_of(self.body_value_id()).shrink_to_lo();
+        invoke_internal!(self, insert, exit, self.get_path_hint(), span);
+      }
+      return;
+    }
+
+    // The step of an early exit was inserted by the exit itself.
+    if let SK::Semi(expr) | SK::Expr(expr) = stmt.kind
+      && Self::is_early_exit(expr)
+    {
+      return;
+    }
+
     self.insert_step_at_node_exit(stmt.hir_id);
   }
 
   fn visit_expr(&mut self, expr: &'tcx hir::Expr) {
     use hir::{ExprKind as EK, LoopSource, MatchSource, StmtKind as SK};
     match expr.kind {
+      // Coroutines, i.e. `async` blocks and the body of an `async fn`,
+      // are stepped through as bodies of their own.
+      EK::Closure(hir::Closure {
+        kind: hir::ClosureKind::Coroutine(_),
+        ..
+      }) => {}
+
+      // An `.await` desugars into a loop which polls the future and
+      // yields while it's pending. None of the loop is visible in the
+      // source, so we step over it as a whole, the step shows the
+      // permissions held once the coroutine is resumed.
+      EK::Match(into_future, [_], MatchSource::AwaitDesugar) => {
+        self.visit_expr(into_future);
+        self.insert_step_at_node_exit(expr.hir_id);
+      }
+
       EK::If(cnd, then, else_opt) => {
         // For the generic case we can take the use the opening brace of each branch
         // target as the span.
@@ -606,13 +1152,15 @@ impl<'a, 'tcx: 'a> HirVisitor<'tcx> for HirStepPoints<'a, 'tcx> {
         let mut entry_to_spans = HashMap::default();
 
         // Insert the location and span for the then branch
-        if let Some(then_entry) = self.get_node_entry(then.hir_id) {
+        let then_entry = self.get_node_entry(then.hir_id);
+        if let Some(then_entry) = then_entry {
           let then_span = self.span_of(then.hir_id).shrink_to_lo();
           entry_to_spans.insert(then_entry, then_span);
         }
 
         // Insert the location and span for the else branch
-        if let Some(else_entry) = self.get_node_entry(els.hir_id) {
+        let else_entry = self.get_node_entry(els.hir_id);
+        if let Some(else_entry) = else_entry {
           // NOTE: we adjust the span of the break block to
           //       be _after_ the loop.
           let else_span = self.span_of(expr.hir_id).shrink_to_hi();
@@ -620,6 +1168,11 @@ impl<'a, 'tcx: 'a> HirVisitor<'tcx> for HirStepPoints<'a, 'tcx> {
         }
 
         self.handle_expr_if(expr, cnd, then, Some(els), entry_to_spans);
+
+        if let (Some(enter), Some(exit)) = (then_entry, else_entry) {
+          invoke_internal!(self, mark_loop_splits, enter, exit);
+        }
+        self.insert_loop_back_edges(expr.hir_id, then.hir_id);
       }
 
       EK::Loop(
@@ -648,17 +1201,45 @@ impl<'a, 'tcx: 'a> HirVisitor<'tcx> for HirStepPoints<'a, 'tcx> {
         let loop_end = self.span_of(expr.hir_id).shrink_to_hi();
 
         // Iterator::next => None, breaking out of the loop
-        if let Some(none_entry) = self.get_node_entry(none.body.hir_id) {
+        let none_entry = self.get_node_entry(none.body.hir_id);
+        if let Some(none_entry) = none_entry {
           entry_to_spans.insert(none_entry, loop_end);
         }
 
         // Iterator::next => Some(_), execute loop body
-        if let Some(some_entry) = self.get_node_entry(some.body.hir_id) {
+        let some_entry = self.get_node_entry(some.body.hir_id);
+        if let Some(some_entry) = some_entry {
           entry_to_spans.insert(some_entry, loop_start);
         }
 
         #[allow(clippy::needless_borrow)]
         self.handle_expr_match(expr, cnd, &arms, entry_to_spans);
+
+        if let (Some(enter), Some(exit)) = (some_entry, none_entry) {
+          invoke_internal!(self, mark_loop_splits, enter, exit);
+        }
+        self.insert_loop_back_edges(expr.hir_id, some.body.hir_id);
+      }
+
+      // A plain `loop` has no condition to split at, only the
+      // back-edge of each iteration, and it's left by a `break`.
+      EK::Loop(block, _, LoopSource::Loop, _) => {
+        intravisit::walk_expr(self, expr);
+        self.insert_loop_back_edges(expr.hir_id, block.hir_id);
+        self.continue_after_breaks(expr.hir_id);
+      }
+
+      // A labeled block can also be left early by a `break`.
+      EK::Block(block, Some(_)) => {
+        self.visit_block(block);
+        self.continue_after_breaks(expr.hir_id);
+      }
+
+      EK::Ret(_) | EK::Break(..) | EK::Continue(_)
+        if Self::is_early_exit(expr) =>
+      {
+        intravisit::walk_expr(self, expr);
+        self.insert_exit_step(expr);
       }
 
       // NOTE: if a match condition doesn't produce a `switchInt`, there
@@ -713,7 +1294,11 @@ impl<'a, 'tcx: 'a> HirVisitor<'tcx> for HirStepPoints<'a, 'tcx> {
         let entry_to_spans = arms
           .iter()
           .filter_map(|arm| {
-            let id = arm.body.hir_id;
+            // A guarded arm is entered at its guard, which sees the bindings.
+            let id = match arm.guard {
+              Some(_) => arm.hir_id,
+              None => arm.body.hir_id,
+            };
             self
               .get_node_entry(id)
               .map(|entry| (entry, self.span_of(arm.pat.hir_id).shrink_to_hi()))
@@ -724,17 +1309,19 @@ impl<'a, 'tcx: 'a> HirVisitor<'tcx> for HirStepPoints<'a, 'tcx> {
       }
       _ => {
         intravisit::walk_expr(self, expr);
+        self.insert_expr_step(expr);
       }
     }
   }
 
   // NOTE: it's impotant that arms handle path hinting
   fn visit_arm(&mut self, arm: &'tcx hir::Arm) {
+    // Guarded arms are stepped through by `handle_expr_match`, as they
+    // need the branch of the match.
     if arm.guard.is_some() {
-      // TODO: NYI.
       report_unsupported!(
         self,
-        "match arm guards are not yet supported {}",
+        "match arm guards are only supported in a branching match {}",
         self.prettify_node(arm.hir_id)
       );
     }
@@ -781,6 +1368,13 @@ mod tests {
   }
 
   fn compile_and_run(code: impl Into<String>) {
+    compile_and_run_with(code, StepGranularity::Line);
+  }
+
+  fn compile_and_run_with(
+    code: impl Into<String>,
+    granularity: StepGranularity,
+  ) {
     init_testing();
     tu::compile_normal(code, |tcx| {
       tu::for_each_body(tcx, |body_id, wfacts| {
@@ -788,8 +1382,9 @@ mod tests {
         let body = &wfacts.body;
         let mapper = IRMapper::new(tcx, body, GatherMode::IgnoreCleanup);
 
-        let mut visitor = HirStepPoints::make(tcx, body, body_id, &mapper)
-          .expect("Failed to create stepper");
+        let mut visitor =
+          HirStepPoints::make(tcx, body, body_id, &mapper, granularity)
+            .expect("Failed to create stepper");
         visitor.visit_nested_body(body_id);
 
         if let Some(uf) = visitor.get_unsupported_feature() {
@@ -825,6 +1420,12 @@ mod tests {
         compile_and_run($code);
       }
     };
+    (by_expression => $name:ident, $code:expr) => {
+      #[test]
+      fn $name() {
+        compile_and_run_with($code, StepGranularity::Expression);
+      }
+    };
     (should_panic => $name:ident, $code:expr) => {
       #[test]
       #[should_panic]
@@ -898,6 +1499,188 @@ fn test(n: Option<i32>) -> i32 {
 "#
   );
 
+  test_valid_segmented_mir!(
+    match_guard,
+    r#"
+fn test(n: Option<&str>) -> usize {
+  match n {
+      Some(s) if s.is_empty() => 0,
+      Some(s) => s.len(),
+      None => 1,
+  }
+}
+"#
+  );
+
+  test_valid_segmented_mir!(
+    match_guard_fall_through,
+    r#"
+fn test(v: &mut Vec<i32>) {
+  match v.first().copied() {
+      Some(n) if n > 0 => v.push(n),
+      Some(_) => v.clear(),
+      None => {}
+  }
+}
+"#
+  );
+
+  test_valid_segmented_mir!(
+    loop_back_edges,
+    r#"
+fn test(v: &mut Vec<String>) {
+  let mut i = 0;
+  let mut longest = &String::new();
+  while i < v.len() {
+    i += 1;
+    if v[i - 1].is_empty() {
+      continue;
+    }
+    longest = &v[i - 1];
+  }
+  loop {
+    if longest.is_empty() {
+      break;
+    }
+    v.push(longest.clone());
+    longest = &v[0];
+  }
+}
+"#
+  );
+
+  test_valid_segmented_mir!(
+    early_return,
+    r#"
+fn test(v: &mut Vec<String>, s: &str) -> usize {
+  if s.is_empty() {
+    return v.len();
+  }
+  let n = s.len();
+  v.push(s.to_string());
+  if n > 10 {
+    v.clear();
+  }
+  n
+}
+"#
+  );
+
+  test_valid_segmented_mir!(
+    let_else,
+    r#"
+fn test(v: &mut Vec<i32>, inputs: &[&str]) -> Option<i32> {
+  for input in inputs {
+    let Ok(n) = input.parse::<i32>() else {
+      continue;
+    };
+    v.push(n);
+  }
+  let Some(last) = v.last() else {
+    return None;
+  };
+  Some(*last)
+}
+"#
+  );
+
+  test_valid_segmented_mir!(
+    labeled_break_value,
+    r#"
+fn test(v: &mut Vec<String>) -> usize {
+  let first = &v[0];
+  let len = 'search: {
+    if first.is_empty() {
+      break 'search 0;
+    }
+    first.len()
+  };
+  let mut n = 0;
+  let total = loop {
+    if n == len {
+      break n * 2;
+    }
+    n += 1;
+  };
+  v.push(String::from("done"));
+  total
+}
+"#
+  );
+
+  test_valid_segmented_mir!(
+    labeled_continue,
+    r#"
+fn test(grid: &mut Vec<Vec<i32>>) -> i32 {
+  let mut sum = 0;
+  'rows: for row in grid.iter_mut() {
+    for n in row.iter() {
+      if *n < 0 {
+        continue 'rows;
+      }
+    }
+    row.push(0);
+    sum += row.len() as i32;
+  }
+  sum
+}
+"#
+  );
+
+  test_valid_segmented_mir!(
+    by_expression => expr_steps_linear,
+    r#"
+fn f(v: &mut Vec<usize>, n: usize) -> usize { v.push(n); n }
+fn test() {
+  let mut a = vec![1];
+  let x = f(&mut a, a.len());
+  let s = String::from("a") + &a[0].to_string();
+  println!("{x} {s}");
+}
+"#
+  );
+
+  test_valid_segmented_mir!(
+    by_expression => expr_steps_in_branches,
+    r#"
+fn test(v: &mut Vec<i32>) {
+  let ok = !v.is_empty() && v[0] > v.len() as i32;
+  if ok {
+    v.push(v.len() as i32);
+  } else {
+    v.clear();
+  }
+  for x in v.clone().iter() {
+    v.push(*x + v[0]);
+  }
+}
+"#
+  );
+
+  test_valid_segmented_mir!(
+    async_await,
+    r#"
+async fn test(v: &mut Vec<i32>, ready: impl Future<Output = i32>) {
+  let first = &v[0];
+  let n = ready.await;
+  v.push(n + *first);
+}
+"#
+  );
+
+  test_valid_segmented_mir!(
+    async_block,
+    r#"
+fn test(v: &mut Vec<usize>) -> impl Future<Output = usize> {
+  async move {
+    let t = std::future::ready(1).await;
+    v.push(t);
+    v.len()
+  }
+}
+"#
+  );
+
   // -----------------------------------
   // Functions taken from weird_exprs.rs
   //
@@ -992,11 +1775,7 @@ fn canttouchthis() -> usize {
 "#
   );
 
-  // XXX: The HIR constructs that turn into NOPs, e.g., the
-  //      `loop { if break {} }` are not present in the
-  //      simplified MIR, which currently causes a few issues.
   test_valid_segmented_mir!(
-    panics_with "internal error" =>
     weird_exprs_angrydome,
     r#"
 fn angrydome() {
@@ -1120,7 +1899,9 @@ fn punch_card() -> impl std::fmt::Debug {
   // "#
   //   );
 
-  // XXX: arm guards are not currently supported.
+  // XXX: a match on `()` doesn't switch on its discriminant, so the
+  //      branch of the match would be opened at the first `switchInt`
+  //      of the guard, the one of the innermost nested `if`.
   test_valid_segmented_mir!(
     panics_with "unsupported feature" =>
     weird_exprs_match_nested_if,
@@ -1177,10 +1958,7 @@ fn function() {
 "#
   );
 
-  // The match will desugar to something with an
-  // arm guard which are NYI.
   test_valid_segmented_mir!(
-    panics_with "unsupported feature" =>
     weird_exprs_bathroom_stall,
     r#"
 fn bathroom_stall() {
@@ -1191,11 +1969,7 @@ fn bathroom_stall() {
 "#
   );
 
-  // TODO(gavin) what changed in MIR output that causes the
-  // dominator analysis to fail...
-  // Real error message "no open collection dominates bb6[3]"
   test_valid_segmented_mir!(
-    panics_with "internal error" =>
     weird_exprs_closure_matching,
     r#"
 fn closure_matching() {
@@ -1,5 +1,6 @@
 //! Analysis for the “Missing-at” relations.
 
+pub mod focus;
 mod hir_steps;
 #[allow(clippy::similar_names)]
 mod segmented_mir;
@@ -43,6 +44,30 @@ impl std::str::FromStr for PermIncludeMode {
   }
 }
 
+fluid_let!(pub static STEP_GRANULARITY: StepGranularity);
+
+/// How finely the stepper splits the body into steps.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Hash)]
+pub enum StepGranularity {
+  /// One table per line, combining the steps ending on it.
+  Line,
+
+  /// One table after each sub-expression which changes permissions,
+  /// anchored at the source range of the expression.
+  Expression,
+}
+
+impl std::str::FromStr for StepGranularity {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "Line" => Ok(Self::Line),
+      "Expression" => Ok(Self::Expression),
+      _ => Err(format!("Could not parse: {s}")),
+    }
+  }
+}
+
 pub trait Difference {
   type Diff;
 
@@ -56,6 +81,70 @@ pub struct PermissionsStepTable {
   pub from: CharRange,
   pub to: CharRange,
   pub state: Vec<(String, PermissionsDataDiff)>,
+
+  /// Raw pointers created from borrowed places within the step.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub raw_pointers: Vec<RawPointerStep>,
+
+  /// Borrowed places whose loans are held across an `.await` within the step.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub held_across_await: Vec<AwaitStep>,
+
+  /// The part of a loop's control flow stepped over, if any.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub loop_step: Option<LoopStep>,
+
+  /// The source range of the expression stepped over, when stepping
+  /// with [`StepGranularity::Expression`], or of the early exit jumping
+  /// out of the enclosing control flow, e.g. a `break`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub anchor: Option<CharRange>,
+}
+
+/// A step through the control flow of a loop.
+///
+/// These are shown as tables of their own, rather than combined with
+/// the other steps on their line. For example, a loan which is still
+/// live at the back-edge is one that survives into the next iteration.
+#[derive(
+  Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, TS,
+)]
+#[ts(export)]
+pub enum LoopStep {
+  /// From the loop condition into the loop body.
+  Enter,
+
+  /// From the end of an iteration back to the head of the loop.
+  BackEdge,
+
+  /// From the loop condition out of the loop.
+  Exit,
+}
+
+/// A raw pointer created from a place borrowed by live loans.
+///
+/// The loans stop refining the borrowed place as soon as the reference
+/// the pointer was created from is dead, even if the raw pointer itself
+/// is used later on.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct RawPointerStep {
+  pub location: CharRange,
+  pub place: String,
+  pub loans: Vec<LoanKey>,
+}
+
+/// A borrowed place whose loans are live across an `.await`.
+///
+/// While the coroutine is suspended the loans are stored in the future,
+/// so the place stays refined until the coroutine resumes and the
+/// references holding them are no longer used.
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+pub struct AwaitStep {
+  pub location: CharRange,
+  pub place: String,
+  pub loans: Vec<LoanKey>,
 }
 
 /// A collection of [`PermissionsStepTable`] which are to be shown at the same location.
@@ -371,13 +460,20 @@ impl MirSegment {
 
 pub fn compute_permission_steps(
   analysis: &AquascopeAnalysis<'_>,
+  focus: &focus::StepFocus,
 ) -> Result<Vec<PermissionsLineDisplay>> {
   let mode = INCLUDE_MODE.copied().unwrap_or(PermIncludeMode::Changes);
+  let granularity = STEP_GRANULARITY.copied().unwrap_or(StepGranularity::Line);
   let ctxt = &analysis.permissions;
   let ir_mapper = &analysis.ir_mapper;
   let body = &ctxt.body_with_facts.body;
-  let mut hir_visitor =
-    hir_steps::HirStepPoints::make(ctxt.tcx, body, ctxt.body_id, ir_mapper)?;
+  let mut hir_visitor = hir_steps::HirStepPoints::make(
+    ctxt.tcx,
+    body,
+    ctxt.body_id,
+    ir_mapper,
+    granularity,
+  )?;
 
   hir_visitor.visit_nested_body(ctxt.body_id);
 
@@ -389,5 +485,7 @@ pub fn compute_permission_steps(
     bail!(fatal_error);
   }
 
-  hir_visitor.finalize(analysis, mode)
+  let mut steps = hir_visitor.finalize(analysis, mode)?;
+  focus.prune(&mut steps);
+  Ok(steps)
 }
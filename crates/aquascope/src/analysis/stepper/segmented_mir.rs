@@ -35,9 +35,14 @@
 //!   control-flow has been split (by say, a `switchInt`) join segments represent the
 //!   steps needed to unify the control-flow again.
 //!
+//! - Exit segments: a segment representing an early exit, a `return`, `break` or
+//!   `continue`. Like join segments `to` post-dominates `from`, but rather than
+//!   unifying the control-flow of a branch an exit segment leaves it, and ends
+//!   the collection it's in.
+//!
 //! Unless specified, the word 'segment' or 'step' always refers to a linear segment.
 //! Whenever the stepper says "insert a step ending at location L", this will _always_
-//! result in a linear step as the other two variants need to be explicitly handled.
+//! result in a linear step as the other variants need to be explicitly handled.
 //!
 //! To maintain validity we use a recursive tree that incrementally builds up sequences
 //! of linear steps. The tree layout looks (roughly) as follows:
@@ -46,8 +51,10 @@
 //! type LinearSegment = MirSegment
 //! type SplitSegment  = MirSegment
 //! type JoinSegment   = MirSegment
+//! type ExitSegment   = MirSegment
 //!
 //! data ControlFlow = Linear LinearSegment
+//!                  | Exit ExitSegment
 //!                  | Branch
 //!                      { splits :: [SplitSegment]
 //!                      , joins  :: [JoinSegment]
@@ -66,6 +73,16 @@
 //! previously specified by the stepper, or (2) the branch that spawned the builder
 //! is being closed.
 //!
+//! A builder is also destroyed by an early exit, as nothing follows it in the
+//! collection. When closing a branch some of whose paths exited early, the
+//! enclosing collection continues from where the remaining paths meet,
+//! rather than from the convergence of all paths, which lies past the exits.
+//!
+//! Loops don't fit the tree, the step from the end of an iteration back to
+//! the loop head goes against the dominator order. These back-edge steps
+//! are kept aside from the collections, they cover no locations of their own
+//! and exist only to show the permissions carried into the next iteration.
+//!
 //! There is a little more to the process than this, for example: making sure that
 //! branches and segments are created within the natural structure of the MIR and only
 //! inserting steps in previously "unstepped" areas. But for those really curious
@@ -84,7 +101,7 @@ use rustc_index::{Idx, IndexVec};
 use rustc_middle::mir::{BasicBlock, Location};
 use rustc_span::Span;
 
-use super::MirSegment;
+use super::{LoopStep, MirSegment};
 use crate::analysis::ir_mapper::IRMapper;
 
 // --------------------------
@@ -157,6 +174,7 @@ pub(super) struct SegmentData {
   pub(super) segment: MirSegment,
   pub(super) span: Span,
   pub(super) scope: ScopeId,
+  pub(super) loop_step: Option<LoopStep>,
 }
 
 #[derive(Debug)]
@@ -184,6 +202,9 @@ pub(super) struct BranchData {
 #[derive(Copy, Clone, Debug)]
 pub(super) enum CFKind {
   Linear(SegmentId),
+  /// An early exit, the last step of its collection. Only the first
+  /// collection continues after an exit, from where it jumped to.
+  Exit(SegmentId),
   Branch(BranchId),
 }
 
@@ -222,6 +243,7 @@ pub(super) struct SegmentedMirBuilder<'a, 'tcx: 'a> {
   collections: IndexVec<CollectionId, Collection>,
   branches: IndexVec<BranchId, BranchData>,
   segments: IndexVec<SegmentId, SegmentData>,
+  back_edges: Vec<SegmentId>,
   processing: OpenCollections,
   branch_roots: InPlaceUnificationTable<TableId>,
   scope_graph: TransitiveRelationBuilder<ScopeId>,
@@ -234,6 +256,7 @@ pub(super) struct SegmentedMir {
   collections: Frozen<IndexVec<CollectionId, Collection>>,
   branches: Frozen<IndexVec<BranchId, BranchData>>,
   segments: Frozen<IndexVec<SegmentId, SegmentData>>,
+  back_edges: Frozen<Vec<SegmentId>>,
   scopes: TransitiveRelation<ScopeId>,
 }
 
@@ -329,6 +352,11 @@ impl SegmentedMir {
     segment.scope
   }
 
+  /// Steps from the end of a loop iteration back to the loop head.
+  pub fn back_edges(&self) -> impl Iterator<Item = SegmentId> + '_ {
+    self.back_edges.iter().copied()
+  }
+
   pub fn get_collection(&self, cid: CollectionId) -> &Collection {
     &self.collections[cid]
   }
@@ -379,6 +407,7 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
       collections,
       branches: IndexVec::default(),
       segments: IndexVec::default(),
+      back_edges: Vec::default(),
       processing: OpenCollections::default(),
       branch_roots: InPlaceUnificationTable::default(),
       scope_graph: TransitiveRelationBuilder::default(),
@@ -413,6 +442,7 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
     Ok(SegmentedMir {
       first_collection: self.first_collection,
       segments: Frozen::freeze(self.segments),
+      back_edges: Frozen::freeze(self.back_edges),
       branches: Frozen::freeze(self.branches),
       collections: Frozen::freeze(self.collections),
       scopes: self.scope_graph.freeze(),
@@ -513,7 +543,7 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
       .copied()
   }
 
-  fn find_next_switchint(&self, current: Location) -> Option<Location> {
+  pub fn find_next_switchint(&self, current: Location) -> Option<Location> {
     use rustc_middle::mir::{Terminator, TerminatorKind as TK};
     let body = self.mapper.cleaned_graph.body();
 
@@ -623,10 +653,19 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
       // 1. insert the split segment into the branch
       let mut to = sblock.start_location();
       let span = get_span(&mut to);
+      // A path can lead straight to the join, e.g. the exit of a `while`
+      // loop, the split then must not step past it.
+      if let Some(phi) = phi_opt
+        && phi != to
+        && mapper.ldominates(phi, to)
+      {
+        to = phi;
+      }
       let sid = self.segments.push(SegmentData {
         segment: MirSegment::new(location, to),
         span,
         scope,
+        loop_step: None,
       });
       branch.splits.push(sid);
 
@@ -668,6 +707,38 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
     r
   }
 
+  /// Opens a branch rooted at `location` within the open branch `parent`,
+  /// e.g. for the `switchInt` of a match arm guard. Split steps get their
+  /// span from `get_span`, but the branch is closed along with its parent.
+  ///
+  /// Nothing is opened if the `switchInt` already splits a branch, which
+  /// is the case for the guard of a match that didn't need to switch on
+  /// its discriminant first.
+  pub fn open_nested_branch(
+    &mut self,
+    parent: BranchId,
+    location: Location,
+    get_span: impl Fn(&mut Location) -> Span + 'a,
+  ) -> Result<()> {
+    log::debug!("opening nested branch at {location:?}");
+
+    let switch_location =
+      self.find_next_switchint(location).unwrap_or(location);
+    if self
+      .branches
+      .iter()
+      .any(|branch| branch.reach.from == switch_location)
+    {
+      log::debug!("switchInt at {switch_location:?} already splits a branch");
+      return Ok(());
+    }
+
+    let child =
+      self.mk_branch(location, GetSpanner::InsertNew(Box::new(get_span)))?;
+    self.nest_branch(parent, child);
+    Ok(())
+  }
+
   fn open_child_branch(
     &mut self,
     parent: BranchId,
@@ -676,10 +747,15 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
     log::debug!("opening implicit branch at {root:?}");
 
     let child = self.mk_branch(root, GetSpanner::GetFrom(parent))?;
+    self.nest_branch(parent, child);
+    Ok(())
+  }
+
+  /// Close the branch `child` whenever `parent` is closed.
+  fn nest_branch(&mut self, parent: BranchId, child: BranchId) {
     let parent_tid = self.branches[parent].table_id;
     let child_tid = self.branches[child].table_id;
     self.branch_roots.union(parent_tid, child_tid);
-    Ok(())
   }
 
   /// Closes a branch of control flow with an origin root of `location`.
@@ -687,7 +763,7 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
   /// Contrary to previous implementations, the function does not implicitly
   /// add a new segment for all split steps.
   pub fn close_branch(&mut self, bid: BranchId) -> Result<()> {
-    let table_root = self.branches[bid].table_id;
+    let table_root = self.branch_roots.find(self.branches[bid].table_id);
 
     let branches_to_close = self
       .branches
@@ -697,11 +773,14 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
       })
       .collect::<Vec<_>>();
 
+    let mut exited = false;
+    let mut survivors = Vec::default();
     for bid in branches_to_close.into_iter() {
-      let branch = &mut self.branches[bid];
+      let branch = &self.branches[bid];
 
       let nested_collections =
         branch.nested.iter().copied().collect::<HashSet<_>>();
+      exited |= nested_collections.iter().any(|&cid| self.has_exits(cid));
 
       let closed_builders = self
         .processing
@@ -709,6 +788,11 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
         .collect::<Vec<_>>();
 
       log::debug!("Closed builders {:#?}", closed_builders);
+      survivors.extend(closed_builders);
+    }
+
+    if exited {
+      self.continue_after_exits(bid, &survivors);
     }
 
     log::debug!("State after closing branches {:#?}", self.processing);
@@ -716,6 +800,148 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
     Ok(())
   }
 
+  /// Whether some path through the collection `cid` exits early.
+  fn has_exits(&self, cid: CollectionId) -> bool {
+    self.collections[cid].data.iter().any(|kind| match kind {
+      CFKind::Linear(_) => false,
+      CFKind::Exit(_) => true,
+      CFKind::Branch(bid) => self.branches[*bid]
+        .nested
+        .iter()
+        .any(|&cid| self.has_exits(cid)),
+    })
+  }
+
+  /// Continue the collection holding the branch `bid` from where its
+  /// `survivors`, the builders of paths that didn't exit early, meet.
+  ///
+  /// The phi of a branch is the convergence of _all_ its paths, with
+  /// early exits it lies past them, e.g. at the return of the body.
+  /// Continuing from there would skip the code following the branch.
+  fn continue_after_exits(
+    &mut self,
+    bid: BranchId,
+    survivors: &[CollectionBuilder],
+  ) {
+    let mapper = self.mapper;
+    let continue_at = match survivors {
+      [] => return,
+      [survivor] => survivor.current_location,
+      [first, ..] => {
+        let meet = depth_first_search(
+          &mapper.cleaned_graph,
+          first.current_location.block,
+        )
+        .map(|block| block.start_location())
+        .find(|&location| {
+          survivors
+            .iter()
+            .all(|cb| mapper.lpost_dominates(location, cb.current_location))
+        });
+
+        let Some(meet) = meet else {
+          log::debug!("paths of branch {bid:?} don't meet again");
+          return;
+        };
+        meet
+      }
+    };
+
+    let collections = &self.collections;
+    let parent_opt = self.processing.iter_mut().find(|cb| {
+      collections[cb.collection]
+        .data
+        .iter()
+        .any(|kind| matches!(kind, CFKind::Branch(b) if *b == bid))
+    });
+
+    if let Some(parent) = parent_opt {
+      log::debug!("Continuing {parent:?} after early exits at {continue_at:?}");
+      parent.current_location = continue_at;
+    }
+  }
+
+  // ---------------
+  // Loop operations
+
+  /// Mark the split steps entering the loop body at `enter`, and
+  /// leaving the loop at `exit`.
+  pub fn mark_loop_splits(
+    &mut self,
+    enter: Location,
+    exit: Location,
+  ) -> Result<()> {
+    let splits = self
+      .branches
+      .iter()
+      .flat_map(|branch| branch.splits.iter().copied())
+      .collect::<Vec<_>>();
+
+    for sid in splits {
+      let segment_data = &mut self.segments[sid];
+      if segment_data.segment.to == enter {
+        segment_data.loop_step = Some(LoopStep::Enter);
+      } else if segment_data.segment.to == exit {
+        segment_data.loop_step = Some(LoopStep::Exit);
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Add a step for each back-edge to the head of the loop made of `blocks`.
+  ///
+  /// A back-edge jumps to a block dominating its source, the loop head is
+  /// the target dominating all others, those of nested loops included.
+  pub fn insert_back_edges(
+    &mut self,
+    blocks: &HashSet<BasicBlock>,
+    span: Span,
+  ) -> Result<()> {
+    let mapper = self.mapper;
+    let graph = &mapper.cleaned_graph;
+
+    let edges = blocks
+      .iter()
+      .flat_map(|&from| {
+        graph
+          .successors(from)
+          .filter(move |&to| mapper.dominates(to, from))
+          .map(move |to| (from, to))
+      })
+      .collect::<Vec<_>>();
+
+    let Some(head) = edges
+      .iter()
+      .map(|&(_, to)| to)
+      .find(|&head| edges.iter().all(|&(_, to)| mapper.dominates(head, to)))
+    else {
+      log::debug!("no back-edges found in blocks {blocks:?}");
+      return Ok(());
+    };
+
+    let scope = self.current_scope();
+    let body = graph.body();
+    for (from, _) in edges.into_iter().filter(|&(_, to)| to == head) {
+      let from = Location {
+        block: from,
+        statement_index: body.basic_blocks[from].statements.len(),
+      };
+      let segment = MirSegment::new(from, head.start_location());
+      log::debug!("Inserting loop back-edge {segment:?}");
+
+      let sid = self.segments.push(SegmentData {
+        segment,
+        span,
+        scope,
+        loop_step: Some(LoopStep::BackEdge),
+      });
+      self.back_edges.push(sid);
+    }
+
+    Ok(())
+  }
+
   fn find_containing_branch(&self, cid: CollectionId) -> Option<BranchId> {
     self
       .branches
@@ -825,6 +1051,7 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
             segment,
             span,
             scope,
+            loop_step: None,
           };
           log::debug!(
             "Inserting {segment:?} into builder {builder:?} {builder_idx:?}"
@@ -857,6 +1084,142 @@ impl<'a, 'tcx: 'a> SegmentedMirBuilder<'a, 'tcx> {
       }
     }
   }
+
+  /// Insert the step of an early exit at `location`, jumping to `target`.
+  ///
+  /// A jump leaving the collection it's in ends it, and destroys its builder,
+  /// see [`SegmentedMirBuilder::close_branch`] for where the steps continue.
+  /// Jumps staying within the collection, e.g. a `break` out of a loop
+  /// nested in a branch, are inserted as regular steps.
+  pub fn insert_exit(
+    &mut self,
+    location: Location,
+    target: Location,
+    span: Span,
+  ) -> Result<()> {
+    let mapper = self.mapper;
+    let builder_opt = self
+      .processing
+      .enumerate()
+      .find(|(_, cb)| mapper.ldominates(cb.current_location, location));
+
+    let Some((builder_i, &builder)) = builder_opt else {
+      log::warn!(
+        "no open collection dominates exit {location:?} {:#?}",
+        self.processing
+      );
+      return Ok(());
+    };
+
+    let leaves_collection = !mapper
+      .ldominates(builder.current_location, target)
+      || matches!(
+        self.collections[builder.collection].kind,
+        LengthKind::Bounded { phi, .. } if mapper.ldominates(phi, target)
+      );
+
+    if leaves_collection {
+      self.push_exit(builder_i, target, span);
+      Ok(())
+    } else {
+      self.insert(target, None, span)
+    }
+  }
+
+  /// Insert the last step of the body, ending at `location`.
+  ///
+  /// After an early return the end of the body isn't dominated by the
+  /// last step, the step to it is then an exit of the first collection.
+  pub fn insert_end(&mut self, location: Location, span: Span) -> Result<()> {
+    if !matches!(self.find_suitable_collection(location), FindResult::None) {
+      return self.insert(location, None, span);
+    }
+
+    let first = BuilderIdx(0);
+    if self.processing.len() != 1
+      || !self
+        .mapper
+        .lpost_dominates(location, self.processing.get(first).current_location)
+    {
+      log::warn!("no suitable collection for the body end {location:?}");
+      return Ok(());
+    }
+
+    self.push_exit(first, location, span);
+    Ok(())
+  }
+
+  fn push_exit(&mut self, builder_i: BuilderIdx, to: Location, span: Span) {
+    let scope = self.current_scope();
+    let builder = *self.processing.get(builder_i);
+    let segment = MirSegment::new(builder.current_location, to);
+    log::debug!("Inserting exit {segment:?} into builder {builder:?}");
+
+    let sid = self.segments.push(SegmentData {
+      segment,
+      span,
+      scope,
+      loop_step: None,
+    });
+    self.collections[builder.collection]
+      .data
+      .push(CFKind::Exit(sid));
+
+    if builder.collection == self.first_collection {
+      self.processing.get_mut(builder_i).current_location = to;
+    } else {
+      let exited = [builder.collection].into_iter().collect::<HashSet<_>>();
+      let closed_builders = self
+        .processing
+        .drain_collections(&exited)
+        .collect::<Vec<_>>();
+      log::debug!("Closed builders {:#?}", closed_builders);
+    }
+  }
+
+  /// Continue the steps after a loop, or labeled block, entered at `entry`
+  /// from the `target` its `break`s jump to.
+  ///
+  /// Without a condition to branch on, the steps through the loop end
+  /// inside of it, and the code following it is only reached by a `break`.
+  pub fn continue_at(
+    &mut self,
+    entry: Location,
+    target: Location,
+  ) -> Result<()> {
+    let mapper = self.mapper;
+    let builder_opt = self
+      .processing
+      .iter_mut()
+      .find(|cb| mapper.ldominates(entry, cb.current_location));
+
+    match builder_opt {
+      Some(builder) => {
+        log::debug!("Continuing {builder:?} at {target:?}");
+        builder.current_location = target;
+      }
+      None => log::warn!("no open collection within {entry:?}"),
+    }
+
+    Ok(())
+  }
+
+  /// Insert a step ending at `location` only if it continues the
+  /// straight-line control flow of an open collection.
+  ///
+  /// Unlike [`SegmentedMirBuilder::insert`] this never opens a branch,
+  /// locations which can't be inserted linearly are ignored.
+  pub fn insert_if_linear(
+    &mut self,
+    location: Location,
+    path_hint: Option<Location>,
+    span: Span,
+  ) -> Result<()> {
+    match self.find_suitable_collection(location) {
+      FindResult::Linear(_) => self.insert(location, path_hint, span),
+      _ => Ok(()),
+    }
+  }
 }
 
 #[cfg(test)]
@@ -890,6 +1253,7 @@ pub(crate) mod test_exts {
   pub enum BadSegmentKind {
     SplitNoDom,
     JoinNoPostDom,
+    ExitNoPostDom,
     LinearNoDom,
     LinearNoPostDom,
   }
@@ -941,6 +1305,7 @@ pub(crate) mod test_exts {
       for kind in collection.data.iter() {
         match kind {
           CFKind::Linear(sid) => self.is_valid_segment(*sid, ssf, mapper)?,
+          CFKind::Exit(sid) => self.is_valid_exit_segment(*sid, ssf, mapper)?,
           CFKind::Branch(bid) => self.is_valid_branch(*bid, ssf, mapper)?,
         }
       }
@@ -992,6 +1357,28 @@ pub(crate) mod test_exts {
       Ok(())
     }
 
+    fn is_valid_exit_segment(
+      &self,
+      sid: SegmentId,
+      ssf: &mut HashSet<Location>,
+      mapper: &IRMapper,
+    ) -> Result<(), InvalidReason> {
+      let SegmentData { segment: s, .. } = self.get_segment(sid);
+
+      if !mapper.lpost_dominates(s.to, s.from) {
+        return Err(InvalidReason::InvalidSegment {
+          segment: *s,
+          kind: BadSegmentKind::ExitNoPostDom,
+        });
+      }
+
+      for at in s.explode(mapper) {
+        ssf.insert(at);
+      }
+
+      Ok(())
+    }
+
     fn is_valid_segment(
       &self,
       sid: SegmentId,
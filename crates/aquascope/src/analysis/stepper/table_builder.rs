@@ -1,11 +1,18 @@
 //! Convert permissions steps into tables viewable by the frontend.
 
+use rustc_borrowck::consumers::PoloniusRegionVid;
 use rustc_data_structures::{
   self,
   fx::{FxHashMap as HashMap, FxHashSet as HashSet},
 };
-use rustc_middle::mir::{Local, Location, Place};
-use rustc_span::Span;
+use rustc_middle::{
+  mir::{
+    Local, Location, Place, ProjectionElem, Rvalue, StatementKind,
+    TerminatorKind,
+  },
+  ty,
+};
+use rustc_span::{DesugaringKind, Span};
 use rustc_utils::{PlaceExt, SpanExt, test_utils::DUMMY_CHAR_RANGE};
 
 use super::{segmented_mir::*, *};
@@ -16,6 +23,9 @@ use crate::{analysis::permissions::PermissionsCtxt, errors};
 pub(super) struct Table<'tcx> {
   span: Span,
   segment: MirSegment,
+  loop_step: Option<LoopStep>,
+  /// Whether the step is an early exit, e.g. a `return`.
+  exit: bool,
   data: HashMap<Place<'tcx>, PermissionsDataDiff>,
 }
 
@@ -43,6 +53,7 @@ impl<'a, 'tcx: 'a> TableBuilder<'a, 'tcx> {
     start_loc: Location,
     body_span: Span,
     mode: PermIncludeMode,
+    granularity: StepGranularity,
   ) -> Vec<PermissionsLineDisplay> {
     let first_point = self.ctxt.location_to_point(start_loc);
     let first_domain = &self.ctxt.permissions_domain_at_point(first_point);
@@ -63,11 +74,17 @@ impl<'a, 'tcx: 'a> TableBuilder<'a, 'tcx> {
     diffs.entry(seg.to).or_default().push(Table {
       segment: seg,
       span: body_open_brace,
+      loop_step: None,
+      exit: false,
       data: first_diff,
     });
     self.insert_collection(&mut diffs, self.mir.first_collection);
 
-    prettify_permission_steps(self.analysis, diffs, mode)
+    for sid in self.mir.back_edges() {
+      self.insert_segment(&mut diffs, sid);
+    }
+
+    prettify_permission_steps(self.analysis, diffs, mode, granularity)
   }
 
   fn locals_to_filter(&self, scope: ScopeId) -> HashSet<Local> {
@@ -86,23 +103,38 @@ impl<'a, 'tcx: 'a> TableBuilder<'a, 'tcx> {
     for &part in collection.data.iter() {
       match part {
         CFKind::Linear(seg_id) => self.insert_segment(result, seg_id),
+        CFKind::Exit(seg_id) => self.insert_exit(result, seg_id),
         CFKind::Branch(branch_id) => self.insert_branch(result, branch_id),
       }
     }
   }
 
   fn insert_segment(&self, result: &mut Tables<'tcx>, sid: SegmentId) {
+    if let Some(table) = self.segment_table(sid, false) {
+      result.entry(table.segment.to).or_default().push(table);
+    }
+  }
+
+  /// Early exits get a table of their own, see [`prettify_permission_steps`].
+  fn insert_exit(&self, result: &mut Tables<'tcx>, sid: SegmentId) {
+    if let Some(table) = self.segment_table(sid, true) {
+      result.entry(table.segment.to).or_default().push(table);
+    }
+  }
+
+  fn segment_table(&self, sid: SegmentId, exit: bool) -> Option<Table<'tcx>> {
     let ctxt = &self.ctxt;
     let &SegmentData {
       segment,
       span,
       scope,
+      loop_step,
     } = self.mir.get_segment(sid);
 
     let to_filter = self.locals_to_filter(scope);
 
     if segment.from == segment.to {
-      return;
+      return None;
     }
 
     let p0 = ctxt.location_to_point(segment.from);
@@ -123,14 +155,14 @@ impl<'a, 'tcx: 'a> TableBuilder<'a, 'tcx> {
       );
     }
 
-    let table = Table {
+    log::info!("saving segment diff {segment:?}");
+    Some(Table {
       segment,
       span,
+      loop_step,
+      exit,
       data: diff,
-    };
-
-    log::info!("saving segment diff {segment:?}");
-    result.entry(segment.to).or_default().push(table);
+    })
   }
 
   // NOTE: when inserting a branch we currently ignore join steps. Within the
@@ -191,20 +223,182 @@ impl<'a, 'tcx: 'a> TableBuilder<'a, 'tcx> {
     result.entry(reach.to).or_default().push(Table {
       span: reach.span(self.ctxt),
       segment: *reach,
+      loop_step: None,
+      exit: false,
       data: attached_here,
     });
   }
 }
 
+/// Steps of some kind grouped by source line, along
+/// with the span of the end of their line.
+type StepsByLine<T> = HashMap<usize, (Span, Vec<T>)>;
+
+/// Find the raw pointers created from a borrowed place, grouped
+/// by the source line on which they're created.
+///
+/// A pointer such as `&mut x as *mut i32` is created from a reborrow
+/// `&raw mut *r`, the loans are those flowing into the region of `r`.
+fn raw_pointers_by_line(
+  analysis: &AquascopeAnalysis,
+) -> StepsByLine<RawPointerStep> {
+  let ctxt = &analysis.permissions;
+  let tcx = ctxt.tcx;
+  let body = &ctxt.body_with_facts.body;
+  let source_map = tcx.sess.source_map();
+  let mut by_line = StepsByLine::<RawPointerStep>::default();
+
+  for (block, data) in body.basic_blocks.iter_enumerated() {
+    for (statement_index, stmt) in data.statements.iter().enumerate() {
+      let StatementKind::Assign(box (_, Rvalue::RawPtr(_, place))) = &stmt.kind
+      else {
+        continue;
+      };
+
+      let location = Location {
+        block,
+        statement_index,
+      };
+      let point = ctxt.location_to_point(location);
+      let Some(origins_at) =
+        ctxt.polonius_output.origin_contains_loan_at.get(&point)
+      else {
+        continue;
+      };
+
+      let mut loans = place
+        .iter_projections()
+        .filter(|(_, elem)| matches!(elem, ProjectionElem::Deref))
+        .filter_map(|(prefix, _)| {
+          match prefix.ty(&body.local_decls, tcx).ty.kind() {
+            ty::Ref(region, _, _) => {
+              Some(PoloniusRegionVid::from(region.as_var()))
+            }
+            _ => None,
+          }
+        })
+        .filter_map(|origin| origins_at.get(&origin))
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>();
+      loans.sort_unstable();
+      loans.dedup();
+
+      let Some(&first_loan) = loans.first() else {
+        continue;
+      };
+
+      let borrowed = ctxt.loan_to_borrow(first_loan).borrowed_place();
+      if !borrowed.is_source_visible(tcx, body) {
+        continue;
+      }
+      let Some(place) = borrowed.to_string(tcx, body) else {
+        continue;
+      };
+
+      let Some(span) = ctxt.location_to_span(location).as_local(body.span)
+      else {
+        continue;
+      };
+
+      log::debug!("Raw pointer from {place} at {location:?}: {loans:?}");
+
+      let line = source_map.lookup_line(span.hi()).unwrap().line;
+      let line_end = source_map.span_extend_to_line(span).shrink_to_hi();
+      let (_, steps) = by_line.entry(line).or_insert((line_end, Vec::new()));
+      steps.push(RawPointerStep {
+        location: analysis.span_to_range(span),
+        place,
+        loans: loans.iter().map(Into::into).collect(),
+      });
+    }
+  }
+
+  by_line
+}
+
+/// Find the loans held across each `.await`, grouped by the
+/// source line of the await.
+///
+/// An `.await` suspends the coroutine at a `Yield` terminator,
+/// every loan live at the yield is held across the await.
+fn loans_across_await_by_line(
+  analysis: &AquascopeAnalysis,
+) -> StepsByLine<AwaitStep> {
+  let ctxt = &analysis.permissions;
+  let tcx = ctxt.tcx;
+  let body = &ctxt.body_with_facts.body;
+  let source_map = tcx.sess.source_map();
+  let mut by_line = StepsByLine::<AwaitStep>::default();
+
+  for (block, data) in body.basic_blocks.iter_enumerated() {
+    let terminator = data.terminator();
+    let span = terminator.source_info.span;
+    if !matches!(terminator.kind, TerminatorKind::Yield { .. })
+      || !span.is_desugaring(DesugaringKind::Await)
+    {
+      continue;
+    }
+
+    let location = body.terminator_loc(block);
+    let point = ctxt.location_to_point(location);
+    let Some(live_loans) = ctxt.polonius_output.loan_live_at.get(&point) else {
+      continue;
+    };
+
+    let Some(span) = span.as_local(body.span) else {
+      continue;
+    };
+
+    let mut loans_by_place = HashMap::<Place<'_>, Vec<LoanKey>>::default();
+    for loan in live_loans {
+      let borrowed = ctxt.loan_to_borrow(*loan).borrowed_place();
+      if borrowed.is_source_visible(tcx, body) {
+        loans_by_place
+          .entry(borrowed)
+          .or_default()
+          .push(loan.into());
+      }
+    }
+
+    let mut steps = loans_by_place
+      .into_iter()
+      .filter_map(|(place, mut loans)| {
+        loans.sort_unstable();
+        Some(AwaitStep {
+          location: analysis.span_to_range(span),
+          place: place.to_string(tcx, body)?,
+          loans,
+        })
+      })
+      .collect::<Vec<_>>();
+    steps.sort_by(|s1, s2| s1.place.cmp(&s2.place));
+
+    log::debug!("Loans held across the await at {location:?}: {steps:?}");
+
+    let line = source_map.lookup_line(span.hi()).unwrap().line;
+    let line_end = source_map.span_extend_to_line(span).shrink_to_hi();
+    let (_, line_steps) = by_line.entry(line).or_insert((line_end, Vec::new()));
+    line_steps.extend(steps);
+  }
+
+  by_line
+}
+
 // Prettify, means:
 // - Remove all places that are not source visible
 // - Remove all tables which are empty
 // - Convert Spans to Ranges
+//
+// When stepping by expression, the tables of a line aren't combined,
+// each keeps the span of its step as an anchor. The same goes for
+// early exits, anchored at the `return`, `break` or `continue`.
 #[allow(clippy::if_not_else)]
 pub(super) fn prettify_permission_steps<'tcx>(
   analysis: &AquascopeAnalysis<'tcx>,
   perm_steps: Tables<'tcx>,
   mode: PermIncludeMode,
+  granularity: StepGranularity,
 ) -> Vec<PermissionsLineDisplay> {
   let ctxt = &analysis.permissions;
   let tcx = ctxt.tcx;
@@ -226,9 +420,13 @@ pub(super) fn prettify_permission_steps<'tcx>(
     errors::get_span_of_first_error(ctxt.def_id.expect_local())
       .and_then(|s| s.as_local(ctxt.body_with_facts.body.span));
   let source_map = tcx.sess.source_map();
+  let raw_pointers = raw_pointers_by_line(analysis);
+  let held_across_await = loans_across_await_by_line(analysis);
 
+  // Steps through a loop are kept apart from the others on their line,
+  // as are the steps of each expression and early exit.
   let mut semi_filtered = HashMap::<
-    usize,
+    (usize, Option<LoopStep>, Option<Span>),
     Vec<(MirSegment, Span, Vec<(Place<'tcx>, PermissionsDataDiff)>)>,
   >::default();
 
@@ -241,9 +439,14 @@ pub(super) fn prettify_permission_steps<'tcx>(
     for Table {
       segment,
       span,
+      loop_step,
+      exit,
       data,
     } in v.into_iter()
     {
+      let anchor =
+        (exit || granularity == StepGranularity::Expression).then_some(span);
+
       // Attach the span to the end of the line. Later, all permission
       // steps appearing on the same line will be combined.
       let span = source_map.span_extend_to_line(span).shrink_to_hi();
@@ -267,7 +470,7 @@ pub(super) fn prettify_permission_steps<'tcx>(
         // We'll store things by line number
         let line_num = source_map.lookup_line(span.hi()).unwrap().line;
         semi_filtered
-          .entry(line_num)
+          .entry((line_num, loop_step, anchor))
           .or_default()
           .push((segment, span, entries));
       } else {
@@ -295,9 +498,9 @@ pub(super) fn prettify_permission_steps<'tcx>(
   // set of permissions in the same table. This is kind of weird, we'd
   // rather just show *no change*.
 
-  semi_filtered
+  let mut tables = semi_filtered
     .into_iter()
-    .filter_map(|(line, entries)| {
+    .filter_map(|((line, loop_step, anchor), entries)| {
 
       // Conforming to the above HACK this just takes any (from, to) pair.
       let dummy_char_range = DUMMY_CHAR_RANGE.with(|range| *range);
@@ -349,6 +552,15 @@ pub(super) fn prettify_permission_steps<'tcx>(
         return None;
       }
 
+      // An expression only gets a table if it changes permissions, with
+      // `PermIncludeMode::All` the unchanged places are shown alongside.
+      if granularity == StepGranularity::Expression
+        && anchor.is_some()
+        && combined_table.values().all(PermissionsDataDiff::is_empty)
+      {
+        return None;
+      }
+
       let mut master_table_vec = combined_table
         .into_iter()
         .collect::<Vec<_>>();
@@ -363,12 +575,98 @@ pub(super) fn prettify_permission_steps<'tcx>(
           .into_iter()
           .map(|(place, diff)| (place_to_string!(place), diff))
           .collect::<Vec<_>>(),
+        raw_pointers: Vec::new(),
+        held_across_await: Vec::new(),
+        loop_step,
+        anchor: anchor.map(|span| analysis.span_to_range(span)),
       };
 
-      Some(PermissionsLineDisplay {
-        location: range,
-        state: vec![master_table],
-      })
+      Some((line, loop_step, anchor, range, master_table))
     })
-    .collect::<Vec<_>>()
+    .collect::<Vec<_>>();
+
+  // Show the tables of a line together, loop steps after the others,
+  // and the steps of expressions in the order they end.
+  tables.sort_by_key(|(line, loop_step, anchor, ..)| {
+    (*line, *loop_step, anchor.map(|span| (span.hi(), span.lo())))
+  });
+  let mut displays = Vec::<(usize, PermissionsLineDisplay)>::new();
+  for (line, _, _, range, table) in tables {
+    match displays.last_mut() {
+      Some((last_line, display)) if *last_line == line => {
+        display.state.push(table);
+      }
+      _ => displays.push((line, PermissionsLineDisplay {
+        location: range,
+        state: vec![table],
+      })),
+    }
+  }
+
+  let before_first_error = |span: Span| {
+    first_error_span_opt.is_none_or(|err_span| span.lo() <= err_span.hi())
+  };
+  attach_to_line_tables(
+    analysis,
+    &mut displays,
+    raw_pointers,
+    before_first_error,
+    |table| &mut table.raw_pointers,
+  );
+  attach_to_line_tables(
+    analysis,
+    &mut displays,
+    held_across_await,
+    before_first_error,
+    |table| &mut table.held_across_await,
+  );
+
+  displays.into_iter().map(|(_, display)| display).collect()
+}
+
+/// Attach the steps of each line to the first table on that line outside
+/// of a loop step, or to its first table if all are loop steps. A line
+/// without any table gets a table of its own.
+fn attach_to_line_tables<T>(
+  analysis: &AquascopeAnalysis,
+  displays: &mut Vec<(usize, PermissionsLineDisplay)>,
+  by_line: StepsByLine<T>,
+  before_first_error: impl Fn(Span) -> bool,
+  steps_of: impl Fn(&mut PermissionsStepTable) -> &mut Vec<T>,
+) {
+  for (line, (line_end, steps)) in by_line {
+    if !before_first_error(line_end) {
+      continue;
+    }
+
+    let idx = match displays.binary_search_by_key(&line, |(l, _)| *l) {
+      Ok(idx) => idx,
+      Err(idx) => {
+        let range = analysis.span_to_range(line_end);
+        displays.insert(
+          idx,
+          (line, PermissionsLineDisplay {
+            location: range,
+            state: vec![PermissionsStepTable {
+              from: range,
+              to: range,
+              state: Vec::new(),
+              raw_pointers: Vec::new(),
+              held_across_await: Vec::new(),
+              loop_step: None,
+              anchor: None,
+            }],
+          }),
+        );
+        idx
+      }
+    };
+
+    let tables = &mut displays[idx].1.state;
+    let table = tables
+      .iter()
+      .position(|table| table.loop_step.is_none())
+      .unwrap_or(0);
+    steps_of(&mut tables[table]).extend(steps);
+  }
 }
@@ -0,0 +1,397 @@
+//! Interprocedural permission summaries.
+//!
+//! The permissions analysis runs one body at a time, so at a call site
+//! we only know the permissions needed to _pass_ an argument. A summary
+//! describes what the callee does with each of its parameters, which
+//! makes the signature readable as a permissions contract:
+//!
+//! ```ignore
+//! fn push_one(v: &mut Vec<i32>) { // v: R, *v: RW
+//!   v.push(1);
+//! }
+//!
+//! fn first(v: &Vec<i32>) -> &i32 { // v: R, *v: R, flows to return
+//!   &v[0]
+//! }
+//! ```
+//!
+//! Summaries are computed from the callee's MIR and its [`RegionFlows`],
+//! and call-site boundaries link back to them through a [`SummaryLink`].
+//!
+//! [`RegionFlows`]: crate::analysis::permissions::flow::RegionFlows
+
+use std::cell::RefCell;
+
+use fluid_let::fluid_set;
+use rustc_data_structures::fx::{FxHashMap as HashMap, FxHashSet as HashSet};
+use rustc_hir::{
+  ExprKind, HirId, Node,
+  def::DefKind,
+  def_id::{DefId, LocalDefId},
+};
+use rustc_middle::{
+  mir::{
+    Local, Location, Place, ProjectionElem,
+    visit::{MutatingUseContext, NonMutatingUseContext, PlaceContext, Visitor},
+  },
+  ty::{Ty, TyCtxt, TypeckResults},
+};
+use rustc_utils::{mir::borrowck_facts, source_map::range::CharRange};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::analysis::permissions::{
+  self, ENABLE_FLOW_PERMISSIONS, Origin, Permissions, PermissionsCtxt,
+};
+
+/// What a function body requires of one of its parameters.
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct ParamSummary {
+  /// The parameter pattern as written, e.g. `v` or `(a, b)`.
+  pub name: String,
+  pub range: CharRange,
+
+  /// Permissions the body needs on the parameter itself.
+  pub needs: Permissions,
+
+  /// Permissions the body needs on the data behind the parameter,
+  /// only present for reference parameters.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub needs_deref: Option<Permissions>,
+
+  /// Can a reference within the parameter flow into the return value?
+  pub flows_to_return: bool,
+}
+
+/// The permissions contract of a local function.
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct FunctionSummary {
+  pub function: String,
+  pub signature: CharRange,
+  pub params: Vec<ParamSummary>,
+}
+
+thread_local! {
+  // Summaries by function, as each is shared by all bodies calling it.
+  static SUMMARIES: RefCell<HashMap<LocalDefId, Option<FunctionSummary>>> =
+    RefCell::default();
+}
+
+/// A call-site argument and the callee parameter it is passed to.
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct SummaryLink {
+  #[serde(skip)]
+  pub(crate) def_id: DefId,
+
+  /// The callee, matching [`FunctionSummary::function`].
+  pub function: String,
+
+  /// Index of the parameter, the receiver of a method is `0`.
+  pub param: usize,
+}
+
+fn union(p1: Permissions, p2: Permissions) -> Permissions {
+  Permissions {
+    read: p1.read || p2.read,
+    write: p1.write || p2.write,
+    drop: p1.drop || p2.drop,
+  }
+}
+
+/// Permissions required by a single use of a place.
+fn needs_for_context(context: PlaceContext) -> Option<Permissions> {
+  let (read, write, drop) = match context {
+    PlaceContext::NonMutatingUse(NonMutatingUseContext::Move)
+    | PlaceContext::MutatingUse(MutatingUseContext::Drop) => {
+      (true, false, true)
+    }
+    PlaceContext::NonMutatingUse(_) => (true, false, false),
+    PlaceContext::MutatingUse(MutatingUseContext::Retag)
+    | PlaceContext::NonUse(_) => return None,
+    PlaceContext::MutatingUse(_) => (true, true, false),
+  };
+
+  Some(Permissions { read, write, drop })
+}
+
+/// Collects the permissions needed on each argument, and on the data
+/// behind it, from the uses in a MIR body.
+struct ParamUses {
+  arg_count: usize,
+  needs: Vec<Permissions>,
+  needs_deref: Vec<Permissions>,
+}
+
+impl<'tcx> Visitor<'tcx> for ParamUses {
+  fn visit_place(
+    &mut self,
+    place: &Place<'tcx>,
+    context: PlaceContext,
+    _location: Location,
+  ) {
+    let idx = place.local.as_usize();
+    if idx == 0 || idx > self.arg_count {
+      return;
+    }
+
+    let Some(needs) = needs_for_context(context) else {
+      return;
+    };
+
+    let i = idx - 1;
+    if place.projection.contains(&ProjectionElem::Deref) {
+      // Using the data behind a reference requires reading the reference.
+      self.needs_deref[i] = union(self.needs_deref[i], needs);
+      self.needs[i] = union(self.needs[i], Permissions {
+        read: true,
+        write: false,
+        drop: false,
+      });
+    } else {
+      self.needs[i] = union(self.needs[i], needs);
+    }
+  }
+}
+
+fn regions_in<'tcx>(ty: Ty<'tcx>) -> impl Iterator<Item = Origin> + 'tcx {
+  ty.walk().filter_map(|arg| {
+    let region = arg.as_region()?;
+    region.is_var().then(|| region.as_var().into())
+  })
+}
+
+/// Which arguments of the body have a region that flows into the return type.
+fn params_flowing_to_return(ctxt: &PermissionsCtxt) -> Vec<bool> {
+  let body = &ctxt.body_with_facts.body;
+  let region_flows = ctxt.region_flows();
+
+  // Regions not involved in any constraint can't flow anywhere.
+  let constrained = ctxt
+    .polonius_input_facts
+    .subset_base
+    .iter()
+    .flat_map(|&(o1, o2, _)| [o1, o2])
+    .collect::<HashSet<_>>();
+
+  let return_regions = regions_in(body.return_ty())
+    .filter(|o| constrained.contains(o))
+    .collect::<Vec<_>>();
+
+  body
+    .args_iter()
+    .map(|arg| {
+      regions_in(body.local_decls[arg].ty)
+        .filter(|o| constrained.contains(o))
+        .any(|from| {
+          return_regions
+            .iter()
+            .any(|&to| region_flows.may_flow_into(from, to))
+        })
+    })
+    .collect()
+}
+
+/// Compute the permissions summary of the local function `def_id`,
+/// or reuse it if it was already computed.
+///
+/// Returns `None` if `def_id` isn't a function with a body.
+pub fn summarize(tcx: TyCtxt, def_id: LocalDefId) -> Option<FunctionSummary> {
+  if let Some(summary) =
+    SUMMARIES.with_borrow(|summaries| summaries.get(&def_id).cloned())
+  {
+    return summary;
+  }
+
+  let summary = compute_summary(tcx, def_id);
+  SUMMARIES.with_borrow_mut(|summaries| {
+    summaries.insert(def_id, summary.clone());
+  });
+  summary
+}
+
+fn compute_summary(tcx: TyCtxt, def_id: LocalDefId) -> Option<FunctionSummary> {
+  if !matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn) {
+    return None;
+  }
+
+  let hir_body = tcx.hir_maybe_body_owned_by(def_id)?;
+  let body_id = hir_body.id();
+
+  // Flows are required to know what reaches the return value.
+  fluid_set!(ENABLE_FLOW_PERMISSIONS, true);
+  let bwf = borrowck_facts::get_body_with_borrowck_facts(tcx, def_id);
+  let ctxt = permissions::compute(tcx, body_id, bwf);
+  let body = &ctxt.body_with_facts.body;
+
+  let mut uses = ParamUses {
+    arg_count: body.arg_count,
+    needs: vec![Permissions::bottom(); body.arg_count],
+    needs_deref: vec![Permissions::bottom(); body.arg_count],
+  };
+  uses.visit_body(body);
+
+  let flows_to_return = params_flowing_to_return(&ctxt);
+
+  let source_map = tcx.sess.source_map();
+  let params = hir_body
+    .params
+    .iter()
+    .enumerate()
+    .filter_map(|(i, param)| {
+      let local = Local::from_usize(i + 1);
+      let is_ref = body.local_decls[local].ty.is_ref();
+      Some(ParamSummary {
+        name: source_map.span_to_snippet(param.pat.span).ok()?,
+        range: CharRange::from_span(param.pat.span, source_map).ok()?,
+        needs: uses.needs[i],
+        needs_deref: is_ref.then_some(uses.needs_deref[i]),
+        flows_to_return: flows_to_return[i],
+      })
+    })
+    .collect::<Vec<_>>();
+
+  let signature =
+    CharRange::from_span(tcx.def_span(def_id), source_map).ok()?;
+
+  log::debug!("Summary for {def_id:?}: {params:#?}");
+
+  Some(FunctionSummary {
+    function: tcx.def_path_str(def_id),
+    signature,
+    params,
+  })
+}
+
+/// If `hir_id` is an argument, or receiver, of a call to a local
+/// function, link it to the corresponding parameter of the callee.
+pub(crate) fn summary_link(
+  tcx: TyCtxt,
+  typeck_res: &TypeckResults,
+  hir_id: HirId,
+) -> Option<SummaryLink> {
+  let (_, Node::Expr(parent)) = tcx.hir_parent_iter(hir_id).next()? else {
+    return None;
+  };
+
+  let (def_id, param) = match parent.kind {
+    ExprKind::Call(func, args) => {
+      let ExprKind::Path(qpath) = &func.kind else {
+        return None;
+      };
+      let def_id = typeck_res.qpath_res(qpath, func.hir_id).opt_def_id()?;
+      let param = args.iter().position(|arg| arg.hir_id == hir_id)?;
+      (def_id, param)
+    }
+    ExprKind::MethodCall(_, rcvr, args, _) => {
+      let def_id = typeck_res.type_dependent_def_id(parent.hir_id)?;
+      let param = if rcvr.hir_id == hir_id {
+        0
+      } else {
+        args.iter().position(|arg| arg.hir_id == hir_id)? + 1
+      };
+      (def_id, param)
+    }
+    _ => return None,
+  };
+
+  let local_def_id = def_id.as_local()?;
+  if !matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn)
+    || tcx.hir_maybe_body_owned_by(local_def_id).is_none()
+  {
+    return None;
+  }
+
+  Some(SummaryLink {
+    def_id,
+    function: tcx.def_path_str(def_id),
+    param,
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::test_utils as tu;
+
+  const R: Permissions = Permissions {
+    read: true,
+    write: false,
+    drop: false,
+  };
+
+  const RW: Permissions = Permissions {
+    read: true,
+    write: true,
+    drop: false,
+  };
+
+  const RO: Permissions = Permissions {
+    read: true,
+    write: false,
+    drop: true,
+  };
+
+  fn def_id_of(tcx: TyCtxt, name: &str) -> LocalDefId {
+    tcx
+      .hir_body_owners()
+      .find(|def_id| tcx.def_path_str(*def_id) == name)
+      .unwrap()
+  }
+
+  fn summary_of(tcx: TyCtxt, name: &str) -> FunctionSummary {
+    summarize(tcx, def_id_of(tcx, name)).unwrap()
+  }
+
+  #[test]
+  fn function_summaries() {
+    let code = r"
+fn push_one(v: &mut Vec<i32>) {
+  v.push(1);
+}
+
+fn first(v: &Vec<i32>, _n: usize) -> &i32 {
+  &v[0]
+}
+
+fn consume(s: String) -> usize {
+  let t = s;
+  t.len()
+}
+";
+    tu::compile_normal(code, |tcx| {
+      let push_one = summary_of(tcx, "push_one");
+      let [v] = push_one.params.as_slice() else {
+        panic!("{push_one:#?}");
+      };
+      assert_eq!(v.name, "v");
+      assert_eq!(v.needs, R);
+      assert_eq!(v.needs_deref, Some(RW));
+      assert!(!v.flows_to_return);
+
+      let first = summary_of(tcx, "first");
+      let [v, n] = first.params.as_slice() else {
+        panic!("{first:#?}");
+      };
+      assert_eq!(v.needs_deref, Some(R));
+      assert!(v.flows_to_return);
+      assert_eq!(n.needs_deref, None);
+      assert!(!n.flows_to_return);
+
+      let consume = summary_of(tcx, "consume");
+      let [s] = consume.params.as_slice() else {
+        panic!("{consume:#?}");
+      };
+      assert_eq!(s.needs, RO);
+      assert_eq!(s.needs_deref, None);
+
+      // Later calls reuse the summary computed above.
+      let def_id = def_id_of(tcx, "push_one");
+      assert!(
+        SUMMARIES.with_borrow(|summaries| summaries.contains_key(&def_id))
+      );
+    });
+  }
+}
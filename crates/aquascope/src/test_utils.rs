@@ -14,20 +14,24 @@ use rustc_utils::{
   BodyExt, OperandExt,
   mir::{borrowck_facts, location_or_arg::LocationOrArg},
   source_map::{
-    find_bodies::find_bodies,
     range::{self, CharRange, ToSpan},
     spanner::Spanner,
   },
   test_utils::{DUMMY_FILE, DUMMY_FILE_NAME, StringLoader},
 };
+use serde::Serialize;
 
 use crate::{
   analysis::{
     self, AquascopeAnalysis,
     boundaries::{PermissionsBoundary, compute_permission_boundaries},
-    permissions::{ENABLE_FLOW_PERMISSIONS, Permissions},
+    find_analysis_bodies,
+    permissions::{
+      ENABLE_FLOW_PERMISSIONS, FLOW_ENGINE, FlowEngine, Permissions,
+    },
     stepper::{
-      self, PermIncludeMode, PermissionsDataDiff, compute_permission_steps,
+      self, PermIncludeMode, PermissionsDataDiff, PermissionsStepTable,
+      compute_permission_steps, focus::StepFocus,
     },
   },
   errors::{self, silent::silent_session},
@@ -67,6 +71,8 @@ static CFG_HASH: &str = "////!";
 #[derive(Debug, Default)]
 pub(crate) struct TestFileConfig {
   show_flows: Option<bool>,
+  show_tables: Option<bool>,
+  step_by_expression: Option<bool>,
 }
 
 fn split_test_source(
@@ -186,6 +192,12 @@ pub(crate) fn load_test_from_file(
     if line.starts_with(CFG_HASH) && line.contains("show-flows") {
       cfg.show_flows = Some(true);
     }
+    if line.starts_with(CFG_HASH) && line.contains("show-tables") {
+      cfg.show_tables = Some(true);
+    }
+    if line.starts_with(CFG_HASH) && line.contains("step-by-expression") {
+      cfg.step_by_expression = Some(true);
+    }
   }
 
   Ok((source, cfg))
@@ -325,23 +337,64 @@ pub fn test_boundaries_in_file(
   inner().unwrap()
 }
 
+/// Check that the imperative and datalog flow engines classify
+/// every region constraint in `path` the same way.
+pub fn test_flow_engines_agree_in_file(path: &Path) {
+  let inner = || -> Result<()> {
+    let (source, _) = load_test_from_file(path)?;
+    compile_normal(source, |tcx| {
+      for_each_body(tcx, |body_id, body_with_facts| {
+        fluid_set!(ENABLE_FLOW_PERMISSIONS, true);
+        fluid_set!(FLOW_ENGINE, FlowEngine::CrossCheck);
+        let ctxt = analysis::compute_permissions(tcx, body_id, body_with_facts);
+        let disagreements = ctxt.region_flows().disagreements();
+        assert!(
+          disagreements.is_empty(),
+          "flow engines disagree in {}: {disagreements:#?}",
+          path.display()
+        );
+      })
+    });
+
+    Ok(())
+  };
+
+  inner().unwrap()
+}
+
+/// The steps ending on a line, as given to the snapshot of [`test_steps_in_file`].
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LineSteps {
+  /// The permission changes of all tables on the line.
+  Changes(Vec<(String, PermissionsDataDiff)>),
+
+  /// The tables on the line, for test files configured with `show-tables`.
+  /// These also include e.g. the raw pointers created on the line.
+  Tables(Vec<PermissionsStepTable>),
+}
+
 pub fn test_steps_in_file(
   path: &Path,
-  assert_snap: impl Fn(String, Vec<(usize, Vec<(String, PermissionsDataDiff)>)>)
-  + Send
-  + Sync
-  + Copy,
+  assert_snap: impl Fn(String, Vec<(usize, LineSteps)>) + Send + Sync + Copy,
 ) {
-  use stepper::INCLUDE_MODE;
+  use stepper::{INCLUDE_MODE, STEP_GRANULARITY, StepGranularity};
 
   let inner = || -> Result<()> {
-    let (source, _) = load_test_from_file(path)?;
+    let (source, cfg) = load_test_from_file(path)?;
+    let show_tables = cfg.show_tables.unwrap_or(false);
+    let granularity = if cfg.step_by_expression.unwrap_or(false) {
+      StepGranularity::Expression
+    } else {
+      StepGranularity::Line
+    };
     compile_normal(source, move |tcx| {
       for_each_body(tcx, |body_id, _body_with_facts| {
         let ctxt = AquascopeAnalysis::new(tcx, body_id);
         let tag = analysis_snapshot_tag(&ctxt);
         fluid_set!(INCLUDE_MODE, PermIncludeMode::Changes);
-        let body_steps = compute_permission_steps(&ctxt)
+        fluid_set!(STEP_GRANULARITY, granularity);
+        let body_steps = compute_permission_steps(&ctxt, &StepFocus::default())
           .expect("Permission steps failed in test");
 
         // NOTE: we normalize the permission steps to be
@@ -354,6 +407,9 @@ pub fn test_steps_in_file(
             let span = pss.location.to_span(ctxt.permissions.tcx).unwrap();
             let source_map = tcx.sess.source_map();
             let line_num = source_map.lookup_line(span.hi()).unwrap().line;
+            if show_tables {
+              return (line_num, LineSteps::Tables(pss.state));
+            }
             // FIXME: we shouldn't flatten the tables together, this was only a
             // quick fix for the tests.
             let inner_info = pss
@@ -361,7 +417,7 @@ pub fn test_steps_in_file(
               .into_iter()
               .flat_map(|ps| ps.state)
               .collect::<Vec<_>>();
-            (line_num, inner_info)
+            (line_num, LineSteps::Changes(inner_info))
           })
           .collect::<Vec<_>>();
 
@@ -448,16 +504,18 @@ pub fn for_each_body<'tcx>(
   tcx: TyCtxt<'tcx>,
   mut f: impl FnMut(BodyId, &'tcx BodyWithBorrowckFacts<'tcx>),
 ) {
-  find_bodies(tcx).into_iter().for_each(|(_, body_id)| {
-    let def_id = tcx.hir_body_owner_def_id(body_id);
-    errors::track_body_diagnostics(def_id);
-    let body_with_facts =
-      borrowck_facts::get_body_with_borrowck_facts(tcx, def_id);
+  find_analysis_bodies(tcx)
+    .into_iter()
+    .for_each(|(_, body_id)| {
+      let def_id = tcx.hir_body_owner_def_id(body_id);
+      errors::track_body_diagnostics(def_id);
+      let body_with_facts =
+        borrowck_facts::get_body_with_borrowck_facts(tcx, def_id);
 
-    log::debug!("{}", body_with_facts.body.to_string(tcx).unwrap());
+      log::debug!("{}", body_with_facts.body.to_string(tcx).unwrap());
 
-    f(body_id, body_with_facts);
-  })
+      f(body_id, body_with_facts);
+    })
 }
 
 pub fn compile_normal(
@@ -20,3 +20,10 @@ fn boundaries() {
     });
   });
 }
+
+#[test_log::test]
+fn flow_engines_agree() {
+  test_utils::run_in_dir("boundaries", |path| {
+    test_utils::test_flow_engines_agree_in_file(path);
+  });
+}
@@ -1,6 +1,6 @@
 #![feature(rustc_private)]
 
-use aquascope::test_utils;
+use aquascope::test_utils::{self, LineSteps};
 
 #[test_log::test]
 fn stepper() {
@@ -13,9 +13,11 @@ fn stepper() {
       // Sort the nested arrays by Place string
       let mut state = state
         .into_iter()
-        .map(|(s, mut vec)| {
-          vec.sort_unstable_by_key(|v| v.0.clone());
-          (s, vec)
+        .map(|(s, mut steps)| {
+          if let LineSteps::Changes(vec) = &mut steps {
+            vec.sort_unstable_by_key(|v| v.0.clone());
+          }
+          (s, steps)
         })
         .collect::<Vec<_>>();
 
@@ -1,15 +1,29 @@
 use std::{
   borrow::Cow,
-  env,
+  env, io,
+  path::{Path, PathBuf},
   process::{Command, exit},
+  sync::Arc,
   time::Instant,
 };
 
 use aquascope::{
   analysis::{
     self, AnalysisOutput, AquascopeError, AquascopeResult,
-    permissions::ENABLE_FLOW_PERMISSIONS,
-    stepper::{INCLUDE_MODE, PermIncludeMode},
+    boundaries::{
+      PermissionsBoundary,
+      render::{OutputFormat, render_violations},
+      suggest::{ENABLE_SUGGESTIONS, SourceEdit, apply_edits, edited_position},
+    },
+    find_analysis_bodies,
+    permissions::{
+      ENABLE_FLOW_PERMISSIONS, FLOW_ENGINE, FLOW_ENGINE_DEFAULT, FlowEngine,
+      facts::dump_body_facts,
+    },
+    stepper::{
+      INCLUDE_MODE, PermIncludeMode, STEP_GRANULARITY, StepGranularity,
+      focus::{LineRange, PathMatcher, STEP_FOCUS, StepFocus},
+    },
   },
   errors::{
     initialize_error_tracking, silent::silent_session, track_body_diagnostics,
@@ -20,7 +34,8 @@ use fluid_let::fluid_set;
 use rustc_interface::interface::Result as RustcResult;
 use rustc_middle::ty::TyCtxt;
 use rustc_plugin::{CrateFilter, RustcPlugin, RustcPluginArgs, Utf8Path};
-use rustc_utils::{mir::borrowck_facts, source_map::find_bodies::find_bodies};
+use rustc_span::source_map::{FileLoader, RealFileLoader};
+use rustc_utils::mir::borrowck_facts;
 use serde::{self, Deserialize, Serialize};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -41,12 +56,43 @@ enum AquascopeCommand {
     #[clap(long)]
     steps_include_mode: Option<PermIncludeMode>,
 
+    /// Step through each line, or through each expression changing permissions.
+    #[clap(long)]
+    steps_granularity: Option<StepGranularity>,
+
+    /// Only step through this path, or the paths matching a regex given
+    /// as `re:REGEX`. May be repeated.
+    #[clap(long)]
+    focus_path: Vec<PathMatcher>,
+
+    /// Only step through these lines, given as `START-END` or `LINE`.
+    #[clap(long)]
+    focus_lines: Option<LineRange>,
+
     #[clap(long)]
     show_flows: bool,
+
+    /// The engine used to classify region flows.
+    #[clap(long)]
+    flow_engine: Option<FlowEngine>,
+
+    /// Print the analysis as JSON, or violations as compiler-style text.
+    #[clap(long)]
+    format: Option<OutputFormat>,
+
+    /// Suggest fixes for violations, checked by analyzing the fixed source.
+    #[clap(long)]
+    suggest_fixes: bool,
   },
 
   Interpreter,
 
+  /// Write the Polonius and Aquascope facts of each body as `.facts` files.
+  Facts {
+    #[clap(long, default_value = "aquascope-facts")]
+    output_dir: String,
+  },
+
   Preload,
   RustcVersion,
 }
@@ -111,21 +157,54 @@ impl RustcPlugin for AquascopePlugin {
     match plugin_args.command {
       Permissions {
         steps_include_mode,
+        steps_granularity,
+        focus_path,
+        focus_lines,
         show_flows,
-        ..
+        flow_engine,
+        format,
+        suggest_fixes,
       } => {
         let steps_include_mode =
           steps_include_mode.unwrap_or(PermIncludeMode::Changes);
-        let mut callbacks = AquascopeCallbacks {
-          output: Vec::default(),
-          should_fail: plugin_args.should_fail,
+        let mut callbacks = AquascopeCallbacks::new(
+          plugin_args.should_fail,
           steps_include_mode,
+          steps_granularity.unwrap_or(StepGranularity::Line),
           show_flows,
-          rustc_start: Instant::now(),
+          flow_engine.unwrap_or(FLOW_ENGINE_DEFAULT),
+          suggest_fixes,
+        );
+        callbacks.step_focus = StepFocus {
+          paths: focus_path,
+          lines: focus_lines,
         };
         log::info!("Starting rustc analysis...");
         let _ = run_with_callbacks(&compiler_args, &mut callbacks);
-        postprocess(callbacks.output)
+
+        if suggest_fixes {
+          verify_suggestions(&compiler_args, &mut callbacks);
+        }
+
+        match format.unwrap_or(OutputFormat::Json) {
+          OutputFormat::Json => postprocess(callbacks.output),
+          OutputFormat::Text => {
+            let outputs = callbacks.output.iter().zip(&callbacks.files);
+            for (output, file) in outputs {
+              match (output, file) {
+                (Ok(output), Some(file)) => {
+                  print!(
+                    "{}",
+                    render_violations(&file.name, &file.src, output)
+                  );
+                }
+                (Ok(_), None) => {}
+                (Err(e), _) => log::warn!("Could not analyze body: {e:?}"),
+              }
+            }
+            Ok(())
+          }
+        }
       }
       Interpreter => {
         let mut callbacks = aquascope::interpreter::InterpretCallbacks::new(
@@ -139,6 +218,14 @@ impl RustcPlugin for AquascopePlugin {
             .map_err(|_| AquascopeError::BuildError { range: None }),
         )
       }
+      Facts { output_dir } => {
+        let mut callbacks = FactsCallbacks {
+          output_dir: PathBuf::from(output_dir),
+          output: Vec::default(),
+        };
+        let _ = run_with_callbacks(&compiler_args, &mut callbacks);
+        postprocess(callbacks.output)
+      }
       _ => unreachable!(),
     }
   }
@@ -168,19 +255,184 @@ pub fn run_with_callbacks(
   .map_err(|_| AquascopeError::BuildError { range: None })
 }
 
+/// Keep only the suggested fixes which, once applied to the source, remove
+/// the violation they target without failing any more analyses.
+fn verify_suggestions(
+  compiler_args: &[String],
+  callbacks: &mut AquascopeCallbacks,
+) {
+  let baseline = count_errors(&callbacks.output);
+  let bodies = callbacks.output.len();
+  let AquascopeCallbacks {
+    should_fail,
+    steps_include_mode,
+    steps_granularity,
+    show_flows,
+    flow_engine,
+    ..
+  } = *callbacks;
+
+  // The violations left in the file at `path` once `edits` are applied.
+  let rerun = |path: &Path, src: &str, edits: &[SourceEdit]| {
+    let mut edited = AquascopeCallbacks::new(
+      should_fail,
+      steps_include_mode,
+      steps_granularity,
+      show_flows,
+      flow_engine,
+      false,
+    );
+    edited.file_override = Some((path.to_path_buf(), apply_edits(src, edits)));
+    let built = run_with_callbacks(compiler_args, &mut edited).is_ok();
+    if !built
+      || edited.output.len() != bodies
+      || count_errors(&edited.output) > baseline
+    {
+      return None;
+    }
+
+    let outputs = edited.output.into_iter().zip(edited.files);
+    let violations = outputs
+      .filter(|(_, file)| {
+        file.as_ref().and_then(|f| f.path.as_deref()) == Some(path)
+      })
+      .filter_map(|(output, _)| output.ok())
+      .flat_map(|output| output.boundaries)
+      .filter(|boundary| boundary.is_violation())
+      .collect::<Vec<_>>();
+    Some(violations)
+  };
+
+  let outputs = callbacks.output.iter_mut().zip(&callbacks.files);
+  for (output, file) in outputs {
+    let Ok(output) = output else {
+      continue;
+    };
+
+    for boundary in &mut output.boundaries {
+      let Some(BodyFile {
+        path: Some(path),
+        src,
+        ..
+      }) = file
+      else {
+        boundary.suggestions.clear();
+        continue;
+      };
+
+      let (location, use_kind) = (boundary.location, boundary.use_kind);
+      let path_name = boundary.path_name.clone();
+      let is_target = |edits: &[SourceEdit], other: &PermissionsBoundary| {
+        // An edit may replace the source at the boundary, in which case
+        // it's the same use of the same path wherever it ends up.
+        other.path_name == path_name
+          && match edited_position(location, edits) {
+            Some(location) => other.location == location,
+            None => other.use_kind == use_kind,
+          }
+      };
+
+      boundary.suggestions.retain(|suggestion| {
+        let edits = &suggestion.edits;
+        rerun(path, src.as_str(), edits).is_some_and(|violations| {
+          !violations.iter().any(|other| is_target(edits, other))
+        })
+      });
+    }
+  }
+}
+
+/// The number of failed analyses in the `outputs`.
+fn count_errors(outputs: &[AquascopeResult<AnalysisOutput>]) -> usize {
+  outputs.iter().filter(|output| output.is_err()).count()
+}
+
+/// The source file of an analyzed body.
+struct BodyFile {
+  /// The path of the file on disk, if it has one.
+  path: Option<PathBuf>,
+  /// The name of the file as shown in diagnostics.
+  name: String,
+  src: Arc<String>,
+}
+
+/// Reads the given source in place of the file at `path`.
+struct EditedFileLoader {
+  path: PathBuf,
+  src: String,
+}
+
+impl FileLoader for EditedFileLoader {
+  fn file_exists(&self, path: &Path) -> bool {
+    RealFileLoader.file_exists(path)
+  }
+
+  fn read_file(&self, path: &Path) -> io::Result<String> {
+    if path == self.path {
+      Ok(self.src.clone())
+    } else {
+      RealFileLoader.read_file(path)
+    }
+  }
+
+  fn read_binary_file(&self, path: &Path) -> io::Result<Arc<[u8]>> {
+    RealFileLoader.read_binary_file(path)
+  }
+
+  fn current_directory(&self) -> io::Result<PathBuf> {
+    RealFileLoader.current_directory()
+  }
+}
+
 #[allow(dead_code)]
 struct AquascopeCallbacks {
   output: Vec<AquascopeResult<AnalysisOutput>>,
+  /// The source file of each body in `output`.
+  files: Vec<Option<BodyFile>>,
   should_fail: bool,
   steps_include_mode: PermIncludeMode,
+  steps_granularity: StepGranularity,
   show_flows: bool,
+  flow_engine: FlowEngine,
+  suggest_fixes: bool,
+  step_focus: StepFocus,
+  /// Source to analyze in place of the file at the given path.
+  file_override: Option<(PathBuf, String)>,
   rustc_start: Instant,
 }
 
+impl AquascopeCallbacks {
+  fn new(
+    should_fail: bool,
+    steps_include_mode: PermIncludeMode,
+    steps_granularity: StepGranularity,
+    show_flows: bool,
+    flow_engine: FlowEngine,
+    suggest_fixes: bool,
+  ) -> Self {
+    AquascopeCallbacks {
+      output: Vec::default(),
+      files: Vec::default(),
+      should_fail,
+      steps_include_mode,
+      steps_granularity,
+      show_flows,
+      flow_engine,
+      suggest_fixes,
+      step_focus: StepFocus::default(),
+      file_override: None,
+      rustc_start: Instant::now(),
+    }
+  }
+}
+
 impl rustc_driver::Callbacks for AquascopeCallbacks {
   fn config(&mut self, config: &mut rustc_interface::Config) {
     config.psess_created = Some(silent_session());
     config.override_queries = Some(borrowck_facts::override_queries);
+    if let Some((path, src)) = self.file_override.take() {
+      config.file_loader = Some(Box::new(EditedFileLoader { path, src }));
+    }
   }
 
   fn after_expansion(
@@ -193,23 +445,66 @@ impl rustc_driver::Callbacks for AquascopeCallbacks {
     initialize_error_tracking();
 
     fluid_set!(INCLUDE_MODE, self.steps_include_mode);
+    fluid_set!(STEP_GRANULARITY, self.steps_granularity);
     fluid_set!(ENABLE_FLOW_PERMISSIONS, self.show_flows);
+    fluid_set!(FLOW_ENGINE, self.flow_engine);
+    fluid_set!(ENABLE_SUGGESTIONS, self.suggest_fixes);
+    fluid_set!(STEP_FOCUS, &self.step_focus);
 
     let _start = Instant::now();
 
-    find_bodies(tcx).into_iter().for_each(|(_, body_id)| {
-      // Track diagnostics for the analysis of the current body
-      let def_id = tcx.hir_body_owner_def_id(body_id);
-      track_body_diagnostics(def_id);
-      self.output.push(analysis::AquascopeAnalysis::run(
-        tcx,
-        body_id,
-        self.should_fail,
-      ));
-    });
+    find_analysis_bodies(tcx)
+      .into_iter()
+      .for_each(|(span, body_id)| {
+        // Track diagnostics for the analysis of the current body
+        let def_id = tcx.hir_body_owner_def_id(body_id);
+        track_body_diagnostics(def_id);
+        self.output.push(analysis::AquascopeAnalysis::run(
+          tcx,
+          body_id,
+          self.should_fail,
+        ));
+
+        let source_map = tcx.sess.source_map();
+        let file = source_map.lookup_source_file(span.lo());
+        self.files.push(file.src.clone().map(|src| BodyFile {
+          path: file.name.clone().into_local_path(),
+          name: source_map.filename_for_diagnostics(&file.name).to_string(),
+          src,
+        }));
+      });
 
     log::debug!("Callback analysis took {:?}", self.rustc_start.elapsed());
 
     rustc_driver::Compilation::Stop
   }
 }
+
+struct FactsCallbacks {
+  output_dir: PathBuf,
+  output: Vec<AquascopeResult<PathBuf>>,
+}
+
+impl rustc_driver::Callbacks for FactsCallbacks {
+  fn config(&mut self, config: &mut rustc_interface::Config) {
+    config.psess_created = Some(silent_session());
+    config.override_queries = Some(borrowck_facts::override_queries);
+  }
+
+  fn after_expansion(
+    &mut self,
+    _compiler: &rustc_interface::interface::Compiler,
+    tcx: TyCtxt<'_>,
+  ) -> rustc_driver::Compilation {
+    find_analysis_bodies(tcx)
+      .into_iter()
+      .for_each(|(_, body_id)| {
+        self.output.push(
+          dump_body_facts(tcx, body_id, &self.output_dir)
+            .map_err(AquascopeError::from),
+        );
+      });
+
+    rustc_driver::Compilation::Stop
+  }
+}
@@ -83,6 +83,12 @@ impl AquascopePreprocessor {
         cmd.arg("--show-flows");
       }
 
+      let step_by_expression =
+        block.config.iter().any(|(k, _)| k == "stepByExpression");
+      if step_by_expression && operation == "permissions" {
+        cmd.args(["--steps-granularity", "Expression"]);
+      }
+
       let mut child =
         cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
       if child.wait_timeout(Duration::from_secs(30))?.is_none() {
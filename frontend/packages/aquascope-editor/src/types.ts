@@ -7,7 +7,13 @@ import type { MoveKey } from "./bindings/MoveKey";
 export { CharRange } from "./bindings/CharRange";
 
 export { Permissions } from "./bindings/Permissions";
+export { InteriorMutability } from "./bindings/InteriorMutability";
 export { PermissionsBoundary } from "./bindings/PermissionsBoundary";
+export { UseKind } from "./bindings/UseKind";
+export { LoanExplanation } from "./bindings/LoanExplanation";
+export { SummaryLink } from "./bindings/SummaryLink";
+export { ParamSummary } from "./bindings/ParamSummary";
+export { FunctionSummary } from "./bindings/FunctionSummary";
 
 export { AquascopeError } from "./bindings/AquascopeError";
 export { AnalysisOutput } from "./bindings/AnalysisOutput";
@@ -15,6 +21,7 @@ export { ValueStep } from "./bindings/ValueStep";
 
 export { LoanKey } from "./bindings/LoanKey";
 export { LoanPoints } from "./bindings/LoanPoints";
+export { LoanActivations } from "./bindings/LoanActivations";
 export { LoanRegions } from "./bindings/LoanRegions";
 export { LoanRefined } from "./bindings/LoanRefined";
 
@@ -24,9 +31,13 @@ export { MoveRegions } from "./bindings/MoveRegions";
 
 export { Refiner } from "./bindings/Refiner";
 export { RefinementRegion } from "./bindings/RefinementRegion";
+export { RegionName } from "./bindings/RegionName";
 
 export { PermissionsLineDisplay } from "./bindings/PermissionsLineDisplay";
 export { PermissionsStepTable } from "./bindings/PermissionsStepTable";
+export { RawPointerStep } from "./bindings/RawPointerStep";
+export { AwaitStep } from "./bindings/AwaitStep";
+export { LoopStep } from "./bindings/LoopStep";
 export { PermissionsDataDiff } from "./bindings/PermissionsDataDiff";
 export { PermissionsDiff } from "./bindings/PermissionsDiff";
 